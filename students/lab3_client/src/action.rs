@@ -2,46 +2,400 @@
 ///
 /// Tasks todo: - Some client-side input/output validation
 use std::error::Error;
+use std::fs;
+use std::net::IpAddr;
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use strum_macros::{EnumIter, EnumString, Display};
 use read_input::prelude::*;
+use phonenumber::Mode;
 
 use crate::connection::Connection;
+use crate::messages;
 
-type EmptyResult = Result<(), String>;
-
+/// Fields of an account the server lets us see, the others are None
 #[derive(Serialize, Deserialize, Clone, Debug)]
-struct UserAccount {
+pub struct UserView {
     username: String,
-    password: String,
-    phone_number: String,
-    role: UserRole,
+    phone_number: Option<String>,
+    role: Option<UserRole>,
+    password_changed: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Display, EnumString, EnumIter)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Display, EnumString, EnumIter)]
 enum UserRole {
+    #[default]
     #[strum(serialize = "StandardUser")]
     StandardUser,
     #[strum(serialize = "HR")]
     HR,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub enum PasswordRejection {
+    TooShort { min: usize },
+    TooLong { max: usize },
+    TooWeak {
+        warning: Option<String>,
+        suggestions: Vec<String>,
+    },
+    ContainsBannedWord(String),
+    Breached,
+    Reused,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ValidationError {
+    InvalidUsername,
+    ReservedUsername,
+    InvalidPhone,
+    InvalidPassword,
+    PasswordRejected(Vec<PasswordRejection>),
+    InvalidQuery,
+    InvalidPageSize,
+    InvalidCursor,
+    InvalidEmail,
+    InvalidText,
+    InvalidDate,
+    InvalidManager,
+    NotEditable,
+    InvalidInviteCode,
+    CurrentSession,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ActionError {
+    Unauthorized,
+    ValidationFailed { field: String, reason: ValidationError },
+    NotFound,
+    Conflict,
+    RateLimited { retry_after: u64 },
+    Internal,
+    SessionExpired,
+    PasswordExpired,
+}
+
+impl ActionError {
+    /// Exit code of the client when this is the last error, so that scripts can check the outcome
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ActionError::Unauthorized => 2,
+            ActionError::ValidationFailed { .. } => 3,
+            ActionError::NotFound => 4,
+            ActionError::Conflict => 5,
+            ActionError::RateLimited { .. } => 6,
+            ActionError::Internal => 7,
+            ActionError::SessionExpired => 8,
+            ActionError::PasswordExpired => 9,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    Done,
+    Users(Vec<UserView>),
+    UserPage {
+        users: Vec<UserView>,
+        next_cursor: Option<String>,
+    },
+    Profile(ProfileView),
+    OrgChart(String),
+    History(Vec<FieldChange>),
+    PendingApproval(u32),
+    Pending(Vec<PendingView>),
+    Invitation { code: String, expires_at: String },
+    Sessions(Vec<SessionView>),
+}
+
+pub type ActionResult = Result<Response, ActionError>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Display, EnumIter)]
+enum ProfileField {
+    #[strum(serialize = "Phone number (e.g. +41 78 123 45 67)")]
+    PhoneNumber,
+    #[strum(serialize = "Full name")]
+    FullName,
+    #[strum(serialize = "Email")]
+    Email,
+    #[strum(serialize = "Job title")]
+    JobTitle,
+    #[strum(serialize = "Department")]
+    Department,
+    #[strum(serialize = "Office")]
+    Office,
+    #[strum(serialize = "Manager (username)")]
+    Manager,
+    #[strum(serialize = "Hire date (YYYY-MM-DD)")]
+    HireDate,
+    #[strum(serialize = "Notes")]
+    Notes,
+    #[strum(serialize = "Role")]
+    Role,
+}
+
+impl ProfileField {
+    /// Name of the field used by the server, e.g. in the errors
+    fn name(&self) -> &'static str {
+        match self {
+            ProfileField::PhoneNumber => "phone_number",
+            ProfileField::FullName => "full_name",
+            ProfileField::Email => "email",
+            ProfileField::JobTitle => "job_title",
+            ProfileField::Department => "department",
+            ProfileField::Office => "office",
+            ProfileField::Manager => "manager",
+            ProfileField::HireDate => "hire_date",
+            ProfileField::Notes => "notes",
+            ProfileField::Role => "role",
+        }
+    }
+}
+
+/// Change of a profile field or of the role, the values are None for an empty field or a deleted account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FieldChange {
+    version: u32,
+    field: ProfileField,
+    old_value: Option<String>,
+    new_value: Option<String>,
+    changed_by: String,
+    changed_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UpdateOwnProfileRequest {
+    changes: Vec<(ProfileField, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UpdateProfileRequest {
+    username: String,
+    changes: Vec<(ProfileField, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ViewProfileRequest {
+    username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ChainOfCommandRequest {
+    username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DirectReportsRequest {
+    username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ViewHistoryRequest {
+    username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RevertFieldRequest {
+    username: String,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Profile {
+    full_name: Option<String>,
+    email: Option<String>,
+    job_title: Option<String>,
+    department: Option<String>,
+    office: Option<String>,
+    manager: Option<String>,
+    hire_date: Option<String>,
+    notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProfileView {
+    username: String,
+    role: UserRole,
+    phone_number: String,
+    profile: Profile,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AddUserRequest {
+    username: String,
+    password: String,
+    phone_number: String,
+    role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RegisterRequest {
+    username: String,
+    password: String,
+    phone_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CreateInviteRequest {
+    username: String,
+    role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RedeemInviteRequest {
+    code: String,
+    password: String,
+    phone_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TerminateSessionRequest {
+    id: u64,
+}
+
+/// Connection to the server, anonymous ones have no username
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionView {
+    id: u64,
+    username: Option<String>,
+    peer_ip: Option<IpAddr>,
+    login_time: Option<String>,
+    last_activity: String,
+    current: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ChangeRoleRequest {
+    username: String,
+    role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DeleteUserRequest {
+    username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PendingDecisionRequest {
+    id: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+enum OperationKind {
+    AddUser,
+    ChangeRole,
+    DeleteUser,
+    Register,
+}
+
+/// Sensitive operation waiting for the approval of a second HR user
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingView {
+    id: u32,
+    kind: OperationKind,
+    username: String,
+    role: Option<UserRole>,
+    requested_by: Option<String>,
+    requested_at: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, EnumString)]
+enum MatchMode {
+    #[default]
+    #[strum(ascii_case_insensitive, serialize = "prefix")]
+    Prefix,
+    #[strum(ascii_case_insensitive, serialize = "substring")]
+    Substring,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, EnumString)]
+enum SortField {
+    #[default]
+    #[strum(ascii_case_insensitive, serialize = "username")]
+    Username,
+    #[strum(ascii_case_insensitive, serialize = "role")]
+    Role,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SearchUsersRequest {
+    query: String,
+    mode: MatchMode,
+    role: Option<UserRole>,
+    sort: SortField,
+    descending: bool,
+    cursor: Option<String>,
+    page_size: u32,
+}
+
+const DEFAULT_PAGE_SIZE: u32 = 10;
+
+/// Actions of the menu, the inputs of the selected one are asked before sending it
 #[derive(Serialize, Deserialize, Display, EnumString, EnumIter)]
 pub enum Action {
     #[strum(serialize = "Show users", serialize = "1")]
     ShowUsers,
-    #[strum(serialize = "Change my phone number", serialize = "2")]
-    ChangeOwnPhone,
-    #[strum(serialize = "Change someone's phone number", serialize = "3")]
-    ChangePhone,
+    #[strum(serialize = "Edit my profile", serialize = "2")]
+    UpdateOwnProfile(UpdateOwnProfileRequest),
+    #[strum(serialize = "Edit someone's profile", serialize = "3")]
+    UpdateProfile(UpdateProfileRequest),
     #[strum(serialize = "Add user", serialize = "4")]
-    AddUser,
+    AddUser(AddUserRequest),
     #[strum(serialize = "Login", serialize = "5")]
-    Login,
+    Login(LoginRequest),
     #[strum(serialize = "Logout", serialize = "6")]
     Logout,
-    #[strum(serialize = "Exit", serialize = "7")]
+    #[strum(serialize = "Change my password", serialize = "7")]
+    ChangePassword(ChangePasswordRequest),
+    #[strum(serialize = "Search users", serialize = "8")]
+    SearchUsers(SearchUsersRequest),
+    #[strum(serialize = "View a profile", serialize = "9")]
+    ViewProfile(ViewProfileRequest),
+    #[strum(serialize = "Show someone's managers", serialize = "10")]
+    ChainOfCommand(ChainOfCommandRequest),
+    #[strum(serialize = "Show someone's direct reports", serialize = "11")]
+    DirectReports(DirectReportsRequest),
+    #[strum(serialize = "Export the org chart", serialize = "12")]
+    OrgChart,
+    #[strum(serialize = "Show someone's change history", serialize = "13")]
+    ViewHistory(ViewHistoryRequest),
+    #[strum(serialize = "Revert a change", serialize = "14")]
+    RevertField(RevertFieldRequest),
+    #[strum(serialize = "Change someone's role", serialize = "15")]
+    ChangeRole(ChangeRoleRequest),
+    #[strum(serialize = "Delete a user", serialize = "16")]
+    DeleteUser(DeleteUserRequest),
+    #[strum(serialize = "Show the requests waiting for approval", serialize = "17")]
+    ListPending,
+    #[strum(serialize = "Approve a request", serialize = "18")]
+    Approve(PendingDecisionRequest),
+    #[strum(serialize = "Reject a request", serialize = "19")]
+    Reject(PendingDecisionRequest),
+    #[strum(serialize = "Register", serialize = "20")]
+    Register(RegisterRequest),
+    #[strum(serialize = "Invite a new user", serialize = "21")]
+    CreateInvite(CreateInviteRequest),
+    #[strum(serialize = "Create my account from an invitation", serialize = "22")]
+    RedeemInvite(RedeemInviteRequest),
+    #[strum(serialize = "Show my sessions", serialize = "23")]
+    ListMySessions,
+    #[strum(serialize = "Show all sessions", serialize = "24")]
+    ListSessions,
+    #[strum(serialize = "End a session", serialize = "25")]
+    TerminateSession(TerminateSessionRequest),
+    #[strum(serialize = "Exit", serialize = "26")]
     Exit,
 }
 
@@ -51,100 +405,389 @@ impl Action {
         for i in 1..=actions.len() { println!("{}.\t{}", i, actions.next().unwrap()); }
     }
 
-    pub fn perform(&self, connection: &mut Connection) -> Result<(), Box<dyn Error>> {
-        connection.send(self)?;
-
-        let res = match self {
-            Action::ShowUsers => Action::show_users(connection),
-            Action::ChangeOwnPhone => Action::change_own_phone(connection),
-            Action::ChangePhone => Action::change_phone(connection),
-            Action::AddUser => Action::add_user(connection),
-            Action::Login => Action::login(connection),
-            Action::Logout => Action::logout(connection),
-            Action::Exit => Err("Client disconnected")?
+    /**
+    Parameter: connection - connection to the server
+    Return: Result<Result<(), ActionError>, Box<dyn Error>> - Error sent by the server for this action,
+            the outer error is used when the connection fails
+     **/
+    pub fn perform(self, connection: &mut Connection) -> Result<Result<(), ActionError>, Box<dyn Error>> {
+        let (action, context) = match self {
+            Action::ShowUsers => (Action::ShowUsers, "show_users"),
+            Action::UpdateOwnProfile(_) => (Action::update_own_profile(), "update_profile"),
+            Action::UpdateProfile(_) => (Action::update_profile(), "update_profile"),
+            Action::AddUser(_) => (Action::add_user(), "add_user"),
+            Action::Login(_) => (Action::login(), "login"),
+            Action::Logout => (Action::Logout, "logout"),
+            Action::ChangePassword(_) => (Action::change_password(), "change_password"),
+            Action::SearchUsers(_) => return Action::search_users(connection),
+            Action::ViewProfile(_) => (Action::view_profile(), "view_profile"),
+            Action::ChainOfCommand(_) => (Action::chain_of_command(), "chain_of_command"),
+            Action::DirectReports(_) => (Action::direct_reports(), "direct_reports"),
+            Action::OrgChart => (Action::OrgChart, "org_chart"),
+            Action::ViewHistory(_) => (Action::view_history(), "view_history"),
+            Action::RevertField(_) => (Action::revert_field(), "revert_field"),
+            Action::ChangeRole(_) => (Action::change_role(), "change_role"),
+            Action::DeleteUser(_) => (Action::delete_user(), "delete_user"),
+            Action::ListPending => (Action::ListPending, "list_pending"),
+            Action::Approve(_) => (Action::Approve(ask_request_id()), "approve"),
+            Action::Reject(_) => (Action::Reject(ask_request_id()), "reject"),
+            Action::Register(_) => (Action::register(), "register"),
+            Action::CreateInvite(_) => (Action::create_invite(), "create_invite"),
+            Action::RedeemInvite(_) => (Action::redeem_invite(), "redeem_invite"),
+            Action::ListMySessions => (Action::ListMySessions, "list_sessions"),
+            Action::ListSessions => (Action::ListSessions, "list_sessions"),
+            Action::TerminateSession(_) => (Action::terminate_session(), "terminate_session"),
+            Action::Exit => {
+                connection.send(&Action::Exit)?;
+                return Ok(Ok(()));
+            }
         };
+        connection.send(&action)?;
 
-        res
-    }
-
-    pub fn show_users(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
-        let res: Result<Vec<UserAccount>, String> = connection.receive()?;
-        match res {
-            Ok(users) => {
+        match connection.receive::<ActionResult>()? {
+            Ok(Response::Users(users)) => {
+                if users.is_empty() {
+                    println!("No user found");
+                }
                 for u in users {
-                    println!("{} - {}", u.username, u.phone_number);
+                    println!("{}", format_user(&u));
                 }
             }
-            Err(e) => {println!("Error while showing users: {}", e)}
+            Ok(Response::Profile(profile)) => print_profile(&profile),
+            Ok(Response::OrgChart(dot)) => save_org_chart(&dot),
+            Ok(Response::History(changes)) => {
+                if changes.is_empty() {
+                    println!("No change recorded");
+                }
+                for change in changes {
+                    println!("{}", format_change(&change));
+                }
+            }
+            Ok(Response::Sessions(sessions)) => {
+                for session in sessions {
+                    println!("{}", format_session(&session));
+                }
+            }
+            Ok(Response::Invitation { code, expires_at }) => {
+                println!("Invitation code: {}", format_invite_code(&code));
+                // RFC 3339 date, shown to the minute
+                let expiry = expires_at.get(..16).unwrap_or(&expires_at).replace('T', " ");
+                println!("Give it to the new user, it can be used once until {}", expiry);
+            }
+            Ok(Response::PendingApproval(id)) if context == "register" || context == "redeem_invite" => {
+                println!("Your account (request #{}) is waiting for the approval of HR, you can log in once accepted", id)
+            }
+            Ok(Response::Done) if context == "redeem_invite" => println!("Your account was created, you can log in"),
+            Ok(Response::PendingApproval(id)) => {
+                println!("Request #{} is waiting for the approval of another HR user", id)
+            }
+            Ok(Response::Pending(requests)) => {
+                if requests.is_empty() {
+                    println!("No request waiting for approval");
+                }
+                for request in requests {
+                    println!("{}", format_pending(&request));
+                }
+            }
+            Ok(Response::Done) | Ok(Response::UserPage { .. }) => {}
+            Err(e) => {
+                println!("{}", messages::action_failed(context, &e));
+                return Ok(Err(e));
+            }
         }
 
-        Ok(())
+        Ok(Ok(()))
     }
 
-    pub fn change_own_phone(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
-        let phone_number = input::<String>().msg("Please enter your new phone number: ").get();
-        connection.send(&phone_number)?;
+    fn update_own_profile() -> Action {
+        Action::UpdateOwnProfile(UpdateOwnProfileRequest { changes: ask_profile_changes() })
+    }
 
-        let res = connection.receive::<EmptyResult>()?;
-        if let Err(e) = res {
-            println!("Error while changing phone: {}", e);
-        }
+    fn update_profile() -> Action {
+        let username = input::<String>().msg("Please enter the username: ").get();
+        Action::UpdateProfile(UpdateProfileRequest { username, changes: ask_profile_changes() })
+    }
 
-        Ok(())
+    fn view_profile() -> Action {
+        let username = ask_optional_username("Please enter the username (empty for your own profile): ");
+        Action::ViewProfile(ViewProfileRequest { username })
     }
 
-    pub fn change_phone(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
-        let username = input::<String>().msg("Please enter the username: ").get();
-        let phone_number = input::<String>().msg("Please enter the new phone number: ").get();
-        connection.send(&username)?;
-        connection.send(&phone_number)?;
+    fn chain_of_command() -> Action {
+        let username = ask_optional_username("Please enter the username (empty for your own managers): ");
+        Action::ChainOfCommand(ChainOfCommandRequest { username })
+    }
 
-        let res = connection.receive::<EmptyResult>()?;
-        if let Err(e) = res {
-            println!("Error while changing phone: {}", e);
-        }
+    fn direct_reports() -> Action {
+        let username = ask_optional_username("Please enter the username (empty for your own reports): ");
+        Action::DirectReports(DirectReportsRequest { username })
+    }
+
+    fn view_history() -> Action {
+        let username = input::<String>().msg("Please enter the username: ").get();
+        Action::ViewHistory(ViewHistoryRequest { username })
+    }
 
-        Ok(())
+    fn revert_field() -> Action {
+        let username = input::<String>().msg("Please enter the username: ").get();
+        let version = input::<u32>().msg("Change to revert, the field gets back its value from before it (e.g. 3): ").get();
+        Action::RevertField(RevertFieldRequest { username, version })
     }
 
-    pub fn add_user(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+    fn add_user() -> Action {
         let username = input::<String>().msg("Please enter the username: ").get();
         let password = input::<String>().msg("Please enter the password: ").get();
-        let phone_number = input::<String>().msg("Please enter the phone number: ").get();
+        let phone_number = input::<String>().msg("Please enter the phone number (e.g. +41 78 123 45 67): ").get();
         let role = input::<UserRole>().msg("Please enter the role (HR/StandardUser): ").get();
-        connection.send(&username)?;
-        connection.send(&password)?;
-        connection.send(&phone_number)?;
-        connection.send(&role)?;
-
-        let res = connection.receive::<EmptyResult>()?;
-        if let Err(e) = res {
-            println!("Error while adding user: {}", e);
-        }
+        Action::AddUser(AddUserRequest { username, password, phone_number, role })
+    }
 
-        Ok(())
+    fn change_role() -> Action {
+        let username = input::<String>().msg("Please enter the username: ").get();
+        let role = input::<UserRole>().msg("Please enter the new role (HR/StandardUser): ").get();
+        Action::ChangeRole(ChangeRoleRequest { username, role })
     }
 
-    pub fn login(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
+    fn delete_user() -> Action {
+        let username = input::<String>().msg("Please enter the username: ").get();
+        Action::DeleteUser(DeleteUserRequest { username })
+    }
+
+    fn register() -> Action {
+        let username = input::<String>().msg("Please choose a username: ").get();
+        let password = input::<String>().msg("Please choose a password: ").get();
+        let phone_number = input::<String>().msg("Please enter your phone number (e.g. +41 78 123 45 67): ").get();
+        Action::Register(RegisterRequest { username, password, phone_number })
+    }
+
+    fn create_invite() -> Action {
+        let username = input::<String>().msg("Please enter the username of the new user: ").get();
+        let role = input::<UserRole>().msg("Please enter the role (HR/StandardUser): ").get();
+        Action::CreateInvite(CreateInviteRequest { username, role })
+    }
+
+    fn redeem_invite() -> Action {
+        let code = input::<String>().msg("Please enter your invitation code: ").get();
+        let password = input::<String>().msg("Please choose a password: ").get();
+        let phone_number = input::<String>().msg("Please enter your phone number (e.g. +41 78 123 45 67): ").get();
+        Action::RedeemInvite(RedeemInviteRequest { code, password, phone_number })
+    }
+
+    fn terminate_session() -> Action {
+        let id = input::<u64>().msg("Please enter the number of the session: ").get();
+        Action::TerminateSession(TerminateSessionRequest { id })
+    }
+
+    fn login() -> Action {
         let username = input::<String>().msg("Please enter the username: ").get();
         let password = input::<String>().msg("Please enter the password: ").get();
-        connection.send(&username)?;
-        connection.send(&password)?;
+        Action::Login(LoginRequest { username, password })
+    }
+
+    fn change_password() -> Action {
+        let old_password = input::<String>().msg("Please enter your current password: ").get();
+        let new_password = input::<String>().msg("Please enter your new password: ").get();
+        Action::ChangePassword(ChangePasswordRequest { old_password, new_password })
+    }
+
+    // Asks the search criteria, then shows the results one page at a time
+    fn search_users(connection: &mut Connection) -> Result<Result<(), ActionError>, Box<dyn Error>> {
+        let query = input::<String>().msg("Please enter the beginning or a part of the username (empty for everyone): ").get();
+        let mode = input::<String>().msg("Match the beginning or any part (prefix/substring) [prefix]: ").get();
+        let role = input::<String>().msg("Only show a role (HR/StandardUser) [all]: ").get();
+        let sort = input::<String>().msg("Sort by (username/role) [username]: ").get();
+        let descending = input::<String>().msg("Descending order (y/n) [n]: ").get();
+        let page_size = input::<String>().msg(format!("Results per page [{}]: ", DEFAULT_PAGE_SIZE)).get();
+
+        let mut request = SearchUsersRequest {
+            query,
+            mode: mode.trim().parse().unwrap_or_default(),
+            role: role.trim().parse().ok(),
+            sort: sort.trim().parse().unwrap_or_default(),
+            descending: descending.trim().eq_ignore_ascii_case("y"),
+            cursor: None,
+            page_size: page_size.trim().parse().unwrap_or(DEFAULT_PAGE_SIZE),
+        };
+
+        loop {
+            connection.send(&Action::SearchUsers(request.clone()))?;
+            let (users, next_cursor) = match connection.receive::<ActionResult>()? {
+                Ok(Response::UserPage { users, next_cursor }) => (users, next_cursor),
+                Ok(_) => return Ok(Ok(())),
+                Err(e) => {
+                    println!("{}", messages::action_failed("search_users", &e));
+                    return Ok(Err(e));
+                }
+            };
+
+            if users.is_empty() {
+                println!("No user found");
+            }
+            for u in users {
+                println!("{}", format_user(&u));
+            }
+
+            let cursor = match next_cursor {
+                Some(cursor) => cursor,
+                None => return Ok(Ok(())),
+            };
+            let next = input::<String>().msg("Show the next page (y/n)? ").get();
+            if !next.trim().eq_ignore_ascii_case("y") {
+                return Ok(Ok(()));
+            }
 
-        let res = connection.receive::<EmptyResult>()?;
-        if let Err(e) = res {
-            println!("Error during login: {}", e);
+            // The server sends its banner before reading each action, the next page is asked right away
+            connection.receive::<String>()?;
+            request.cursor = Some(cursor);
         }
+    }
+}
+
+// Asks a username, None if it is left empty
+fn ask_optional_username(msg: &str) -> Option<String> {
+    let username = input::<String>().msg(msg).get();
+    if username.trim().is_empty() { None } else { Some(username) }
+}
+
+// Saves the DOT text of the org chart in a file, or prints it
+fn save_org_chart(dot: &str) {
+    let path = input::<String>().msg("File to save the org chart (empty to print it): ").get();
+    if path.trim().is_empty() {
+        print!("{}", dot);
+        return;
+    }
+    match fs::write(path.trim(), dot) {
+        Ok(()) => println!("Org chart saved, render it with: dot -Tsvg {} -o org_chart.svg", path.trim()),
+        Err(e) => println!("Cannot save the org chart: {}", e),
+    }
+}
+
+// Displays an invitation code in groups of 4 digits, easier to copy, e.g. 3f9a-07c2-...
+fn format_invite_code(code: &str) -> String {
+    code.chars()
+        .collect::<Vec<char>>()
+        .chunks(4)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+// Displays a session on one line, e.g. #4 alice from 127.0.0.1, logged in at ..., last active at ...
+fn format_session(session: &SessionView) -> String {
+    // RFC 3339 dates, shown to the second
+    let time = |date: &str| date.get(..19).unwrap_or(date).replace('T', " ");
+    let mut line = format!("#{} {}", session.id, session.username.as_deref().unwrap_or("anonymous"));
+    if let Some(ip) = session.peer_ip {
+        line.push_str(&format!(" from {}", ip));
+    }
+    if let Some(login_time) = &session.login_time {
+        line.push_str(&format!(", logged in at {}", time(login_time)));
+    }
+    line.push_str(&format!(", last active at {}", time(&session.last_activity)));
+    if session.current {
+        line.push_str(" (this session)");
+    }
+    line
+}
 
-        Ok(())
+// Asks the number of a pending request
+fn ask_request_id() -> PendingDecisionRequest {
+    let id = input::<u32>().msg("Please enter the number of the request: ").get();
+    PendingDecisionRequest { id }
+}
+
+// Displays a pending request on one line, e.g. #2 change role of alice to HR (by bob on 2024-05-01)
+fn format_pending(request: &PendingView) -> String {
+    let role = request.role.as_ref().map(|role| role.to_string()).unwrap_or_default();
+    let operation = match request.kind {
+        OperationKind::AddUser => format!("add {} user {}", role, request.username),
+        OperationKind::ChangeRole => format!("change role of {} to {}", request.username, role),
+        OperationKind::DeleteUser => format!("delete user {}", request.username),
+        OperationKind::Register => format!("registration of {}", request.username),
+    };
+    let date = request.requested_at.get(..10).unwrap_or(&request.requested_at);
+    let author = request.requested_by.clone().unwrap_or_else(|| "the user".to_string());
+    format!("#{} {} (by {} on {})", request.id, operation, author, date)
+}
+
+// Asks the fields to change one by one, an empty value clears the field
+fn ask_profile_changes() -> Vec<(ProfileField, String)> {
+    // The role is changed with its own action, it needs an approval
+    let fields: Vec<ProfileField> = ProfileField::iter().filter(|field| !matches!(field, ProfileField::Role)).collect();
+    for (i, field) in fields.iter().enumerate() {
+        println!("{}.\t{}", i + 1, field);
     }
 
-    pub fn logout(connection: &mut Connection) -> Result<(), Box<dyn Error>> {
-        let res = connection.receive::<EmptyResult>()?;
-        if let Err(e) = res {
-            println!("{}", e);
+    let mut changes = Vec::new();
+    loop {
+        let choice = input::<String>().msg("Field to change (empty to send the changes): ").get();
+        if choice.trim().is_empty() {
+            return changes;
+        }
+        match choice.trim().parse::<usize>().ok().and_then(|i| fields.get(i.wrapping_sub(1))) {
+            Some(field) => {
+                let value = input::<String>().msg(format!("New {} (empty to clear): ", field)).get();
+                changes.push((*field, value));
+            }
+            None => println!("Unknown field"),
         }
+    }
+}
+
+// Displays every field of a profile, the ones that aren't filled in or visible as "-"
+fn print_profile(view: &ProfileView) {
+    let profile = &view.profile;
+    let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+
+    println!("Username:     {}", view.username);
+    println!("Role:         {}", view.role);
+    println!("Phone number: {}", format_phone(&view.phone_number));
+    println!("Full name:    {}", show(&profile.full_name));
+    println!("Email:        {}", show(&profile.email));
+    println!("Job title:    {}", show(&profile.job_title));
+    println!("Department:   {}", show(&profile.department));
+    println!("Office:       {}", show(&profile.office));
+    println!("Manager:      {}", show(&profile.manager));
+    println!("Hire date:    {}", show(&profile.hire_date));
+    println!("Notes:        {}", show(&profile.notes));
+}
+
+// Displays a change of the history on one line, e.g. #3 2024-05-01 12:00 phone number: a -> b (by x)
+fn format_change(change: &FieldChange) -> String {
+    let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    // RFC 3339 date, shown to the minute
+    let date = change.changed_at.get(..16).unwrap_or(&change.changed_at).replace('T', " ");
+    format!(
+        "#{} {} {}: {} -> {} (by {})",
+        change.version,
+        date,
+        messages::field_name(change.field.name()),
+        show(&change.old_value),
+        show(&change.new_value),
+        change.changed_by
+    )
+}
+
+// Displays the visible fields of a user on one line
+fn format_user(user: &UserView) -> String {
+    let mut line = user.username.clone();
+    if let Some(role) = &user.role {
+        line.push_str(&format!(" - {}", role));
+    }
+    if let Some(phone) = &user.phone_number {
+        line.push_str(&format!(" - {}", format_phone(phone)));
+    }
+    if let Some(changed) = &user.password_changed {
+        // RFC 3339 date, only the day is shown
+        line.push_str(&format!(" - password changed on {}", changed.get(..10).unwrap_or(changed)));
+    }
+    line
+}
 
-        Ok(())
+// Displays a number stored in E.164 in the international format, e.g. +41 78 453 98 72
+fn format_phone(phone: &str) -> String {
+    match phonenumber::parse(None, phone) {
+        Ok(number) => number.format().mode(Mode::International).to_string(),
+        Err(_) => phone.to_string(),
     }
 }
@@ -0,0 +1,109 @@
+/// This tool measures the login response time of a running server for an existing and a
+/// non-existing username, to check that the timing doesn't reveal which accounts exist.
+///
+/// Usage: login_timing <existing_username> [samples]
+#[path = "../connection.rs"]
+mod connection;
+#[allow(dead_code)]
+#[path = "../action.rs"]
+mod action;
+#[allow(dead_code)]
+#[path = "../messages.rs"]
+mod messages;
+
+use crate::action::{Action, ActionResult, LoginRequest};
+use crate::connection::Connection;
+use native_tls::{Protocol, TlsConnector};
+use std::env;
+use std::error::Error;
+use std::net::TcpStream;
+use std::process;
+use std::time::{Duration, Instant};
+
+const SERVER_HOST: &str = "localhost";
+const SERVER_PORT: &str = "4444";
+const DEFAULT_SAMPLES: usize = 30;
+const UNKNOWN_USERNAME: &str = "nobodyhere";
+// Must pass the server length check, otherwise the server answers before hashing
+const WRONG_PASSWORD: &str = "Wrong-Password-4-Timing!";
+
+fn connect() -> Result<Connection, Box<dyn Error>> {
+    let connector = TlsConnector::builder()
+        .min_protocol_version(None)
+        .max_protocol_version(Some(Protocol::Tlsv12))
+        .disable_built_in_roots(true)
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()?;
+
+    let stream = TcpStream::connect(format!("{}:{}", SERVER_HOST, SERVER_PORT))?;
+    Ok(Connection::new(connector.connect(SERVER_HOST, stream)?))
+}
+
+// Sends one login attempt and returns the time between the request and the response
+fn time_login(conn: &mut Connection, username: &str) -> Result<Duration, Box<dyn Error>> {
+    conn.receive::<String>()?; // Banner
+    let start = Instant::now();
+    conn.send(&Action::Login(LoginRequest {
+        username: username.to_string(),
+        password: WRONG_PASSWORD.to_string(),
+    }))?;
+    let _response = conn.receive::<ActionResult>()?;
+    Ok(start.elapsed())
+}
+
+// Prints mean, standard deviation and percentiles of the samples in milliseconds
+fn print_stats(label: &str, samples: &mut [Duration]) {
+    samples.sort();
+    let ms: Vec<f64> = samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+    let mean = ms.iter().sum::<f64>() / ms.len() as f64;
+    let variance = ms.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / ms.len() as f64;
+    let percentile = |p: f64| ms[((ms.len() - 1) as f64 * p).round() as usize];
+
+    println!(
+        "{:<12} mean {:>8.2} ms  stddev {:>7.2} ms  min {:>8.2}  p50 {:>8.2}  p95 {:>8.2}  max {:>8.2}",
+        label,
+        mean,
+        variance.sqrt(),
+        percentile(0.0),
+        percentile(0.5),
+        percentile(0.95),
+        percentile(1.0)
+    );
+}
+
+// Usage errors exit with 2, like most command line tools
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} <existing_username> [samples]", program);
+    process::exit(2);
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    let existing = match args.get(1) {
+        Some(username) => username.clone(),
+        None => usage(&args[0]),
+    };
+    let samples = match args.get(2).map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => n,
+        Some(_) => {
+            eprintln!("The number of samples must be a positive integer");
+            usage(&args[0])
+        }
+        None => DEFAULT_SAMPLES,
+    };
+
+    let mut conn = connect()?;
+    let mut existing_times = Vec::with_capacity(samples);
+    let mut unknown_times = Vec::with_capacity(samples);
+
+    // Interleave the attempts so that load variations affect both sets equally
+    for _ in 0..samples {
+        existing_times.push(time_login(&mut conn, &existing)?);
+        unknown_times.push(time_login(&mut conn, UNKNOWN_USERNAME)?);
+    }
+
+    print_stats("existing", &mut existing_times);
+    print_stats("non-existing", &mut unknown_times);
+    Ok(())
+}
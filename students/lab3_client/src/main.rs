@@ -1,30 +1,41 @@
 /// This file is used to configure and start a TLS connection to the server.
 /// On new connections, the `client` function is called.
 ///
+/// Exit codes: 0 if every action succeeded, 1 if the connection failed, otherwise the code of the
+/// error of the last action that failed (see `ActionError::exit_code`)
+///
 /// Tasks todo: - Configure the TLS client properly.
-
 mod connection;
 mod action;
+mod messages;
 
 use std::error::Error;
 use std::fs::File;
 use native_tls::{Certificate, Protocol, TlsConnector};
 use std::io::{Read};
 use std::net::TcpStream;
+use std::process;
 use read_input::prelude::*;
 use crate::action::Action;
 use crate::connection::Connection;
 
-// Called once connected to the server, used to execute actions.
-fn client(conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+// Called once connected to the server, used to execute actions. Returns the exit code.
+fn client(conn: &mut Connection) -> Result<i32, Box<dyn Error>> {
+    let mut exit_code = 0;
     loop {
         let banner = conn.receive::<String>()?;
         println!("{}", banner);
 
         Action::display();
         let action = input::<Action>().msg("Please select: ").get();
+        let exit = matches!(action, Action::Exit);
 
-        action.perform(conn)?;
+        if let Err(e) = action.perform(conn)? {
+            exit_code = e.exit_code();
+        }
+        if exit {
+            return Ok(exit_code);
+        }
         println!();
     }
 }
@@ -56,7 +67,7 @@ fn main() {
         Ok(stream) => stream,
         Err(e) => {
             eprintln!("Failed to connect to server: {}", e);
-            return;
+            process::exit(1);
         }
     };
 
@@ -64,12 +75,17 @@ fn main() {
         Ok(stream) => stream,
         Err(e) => {
             eprintln!("Failed to init TLS: {}", e);
-            return;
+            process::exit(1);
         }
     };
 
     let mut conn = Connection::new(stream);
-    if let Err(e) = client(&mut conn) {
-        eprintln!("{}", e);
+    match client(&mut conn) {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(e) => {
+            // Also happens when the session is ended from another session
+            eprintln!("{}", messages::connection_closed(e.as_ref()));
+            process::exit(1);
+        }
     }
 }
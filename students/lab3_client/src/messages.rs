@@ -0,0 +1,276 @@
+/// This file is used to translate the messages shown for the errors sent by the server.
+/// The language is taken from the LAB3_LANG environment variable, or from LANG, e.g.
+/// LAB3_LANG=fr for French. English is used by default.
+use std::env;
+use std::error::Error;
+use std::sync::OnceLock;
+
+use crate::action::{ActionError, PasswordRejection, ValidationError};
+
+#[derive(Clone, Copy)]
+pub enum Language {
+    English,
+    French,
+}
+
+static LANGUAGE: OnceLock<Language> = OnceLock::new();
+
+/**
+Parameter: None
+Return: Language - Language of the messages, read from the environment once
+ **/
+pub fn language() -> Language {
+    *LANGUAGE.get_or_init(|| {
+        let lang = env::var("LAB3_LANG").or_else(|_| env::var("LANG")).unwrap_or_default();
+        if lang.to_lowercase().starts_with("fr") {
+            Language::French
+        } else {
+            Language::English
+        }
+    })
+}
+
+/**
+Parameters: context - action that failed, e.g. "add_user"
+            error   - error sent by the server
+Return: String - Message explaining why the action failed
+ **/
+pub fn action_failed(context: &str, error: &ActionError) -> String {
+    let action = match (language(), context) {
+        (Language::English, "show_users") => "showing users",
+        (Language::English, "update_profile") => "updating the profile",
+        (Language::English, "view_profile") => "showing the profile",
+        (Language::English, "add_user") => "adding user",
+        (Language::English, "login") => "logging in",
+        (Language::English, "logout") => "logging out",
+        (Language::English, "change_password") => "changing password",
+        (Language::English, "search_users") => "searching users",
+        (Language::English, "chain_of_command") => "showing the managers",
+        (Language::English, "direct_reports") => "showing the direct reports",
+        (Language::English, "org_chart") => "exporting the org chart",
+        (Language::English, "view_history") => "showing the change history",
+        (Language::English, "revert_field") => "reverting the change",
+        (Language::English, "change_role") => "changing the role",
+        (Language::English, "delete_user") => "deleting the user",
+        (Language::English, "list_pending") => "showing the pending requests",
+        (Language::English, "approve") => "approving the request",
+        (Language::English, "reject") => "rejecting the request",
+        (Language::English, "register") => "registering",
+        (Language::English, "create_invite") => "creating the invitation",
+        (Language::English, "redeem_invite") => "creating the account",
+        (Language::English, "list_sessions") => "showing the sessions",
+        (Language::English, "terminate_session") => "ending the session",
+        (Language::English, _) => "performing the action",
+        (Language::French, "show_users") => "de l'affichage des utilisateurs",
+        (Language::French, "update_profile") => "de la modification du profil",
+        (Language::French, "view_profile") => "de l'affichage du profil",
+        (Language::French, "add_user") => "de l'ajout de l'utilisateur",
+        (Language::French, "login") => "de la connexion",
+        (Language::French, "logout") => "de la déconnexion",
+        (Language::French, "change_password") => "du changement de mot de passe",
+        (Language::French, "search_users") => "de la recherche d'utilisateurs",
+        (Language::French, "chain_of_command") => "de l'affichage des responsables",
+        (Language::French, "direct_reports") => "de l'affichage des subordonnés directs",
+        (Language::French, "org_chart") => "de l'export de l'organigramme",
+        (Language::French, "view_history") => "de l'affichage de l'historique",
+        (Language::French, "revert_field") => "de l'annulation de la modification",
+        (Language::French, "change_role") => "du changement de rôle",
+        (Language::French, "delete_user") => "de la suppression de l'utilisateur",
+        (Language::French, "list_pending") => "de l'affichage des demandes en attente",
+        (Language::French, "approve") => "de l'approbation de la demande",
+        (Language::French, "reject") => "du refus de la demande",
+        (Language::French, "register") => "de l'inscription",
+        (Language::French, "create_invite") => "de la création de l'invitation",
+        (Language::French, "redeem_invite") => "de la création du compte",
+        (Language::French, "list_sessions") => "de l'affichage des sessions",
+        (Language::French, "terminate_session") => "de la fermeture de la session",
+        (Language::French, _) => "de l'action",
+    };
+
+    // The expiry isn't related to the action, which wasn't performed
+    if let ActionError::SessionExpired | ActionError::PasswordExpired = error {
+        return action_error(error);
+    }
+    match language() {
+        Language::English => format!("Error while {}: {}", action, action_error(error)),
+        Language::French => format!("Erreur lors {} : {}", action, action_error(error)),
+    }
+}
+
+/**
+Parameter: error - error of the connection
+Return: String - Message explaining that the connection was closed
+ **/
+pub fn connection_closed(error: &dyn Error) -> String {
+    match language() {
+        Language::English => format!("The connection to the server was closed: {}", error),
+        Language::French => format!("La connexion au serveur a été fermée : {}", error),
+    }
+}
+
+/**
+Parameter: error - error sent by the server
+Return: String - Message to display
+ **/
+pub fn action_error(error: &ActionError) -> String {
+    match (language(), error) {
+        (Language::English, ActionError::Unauthorized) => "You can't do this action or your credentials are invalid".to_string(),
+        (Language::English, ActionError::ValidationFailed { field, reason }) => {
+            format!("Invalid {}: {}", field_name(field), validation_error(reason))
+        }
+        (Language::English, ActionError::NotFound) => "Target user or record not found".to_string(),
+        (Language::English, ActionError::Conflict) => "User already exists or is too similar to an existing user".to_string(),
+        (Language::English, ActionError::RateLimited { retry_after }) => {
+            format!("Too many requests, please retry in {} second(s)", retry_after)
+        }
+        (Language::English, ActionError::Internal) => "Internal server error".to_string(),
+        (Language::English, ActionError::SessionExpired) => "Session expired, please log in again".to_string(),
+        (Language::English, ActionError::PasswordExpired) => {
+            "Your password has expired, please change it with \"Change my password\" first".to_string()
+        }
+        (Language::French, ActionError::Unauthorized) => "Action non autorisée ou identifiants invalides".to_string(),
+        (Language::French, ActionError::ValidationFailed { field, reason }) => {
+            format!("{} invalide : {}", field_name(field), validation_error(reason))
+        }
+        (Language::French, ActionError::NotFound) => "Utilisateur ou élément introuvable".to_string(),
+        (Language::French, ActionError::Conflict) => "L'utilisateur existe déjà ou ressemble trop à un utilisateur existant".to_string(),
+        (Language::French, ActionError::RateLimited { retry_after }) => {
+            format!("Trop de requêtes, veuillez réessayer dans {} seconde(s)", retry_after)
+        }
+        (Language::French, ActionError::Internal) => "Erreur interne du serveur".to_string(),
+        (Language::French, ActionError::SessionExpired) => "Session expirée, veuillez vous reconnecter".to_string(),
+        (Language::French, ActionError::PasswordExpired) => {
+            "Votre mot de passe a expiré, veuillez d'abord le changer avec « Change my password »".to_string()
+        }
+    }
+}
+
+/**
+Parameter: field - name of the input sent by the server
+Return: String - Name of the input to display
+ **/
+pub fn field_name(field: &str) -> String {
+    let name = match (language(), field) {
+        (Language::English, "username") => "username",
+        (Language::English, "phone_number") => "phone number",
+        (Language::English, "password") => "password",
+        (Language::English, "old_password") => "current password",
+        (Language::English, "new_password") => "new password",
+        (Language::English, "query") => "search",
+        (Language::English, "page_size") => "page size",
+        (Language::English, "cursor") => "page",
+        (Language::English, "full_name") => "full name",
+        (Language::English, "email") => "email",
+        (Language::English, "job_title") => "job title",
+        (Language::English, "department") => "department",
+        (Language::English, "office") => "office",
+        (Language::English, "manager") => "manager",
+        (Language::English, "hire_date") => "hire date",
+        (Language::English, "notes") => "notes",
+        (Language::English, "role") => "role",
+        (Language::English, "code") => "invitation code",
+        (Language::English, "session") => "session",
+        (Language::French, "username") => "Nom d'utilisateur",
+        (Language::French, "phone_number") => "Numéro de téléphone",
+        (Language::French, "password") => "Mot de passe",
+        (Language::French, "old_password") => "Mot de passe actuel",
+        (Language::French, "new_password") => "Nouveau mot de passe",
+        (Language::French, "query") => "Recherche",
+        (Language::French, "page_size") => "Taille de page",
+        (Language::French, "cursor") => "Page",
+        (Language::French, "full_name") => "Nom complet",
+        (Language::French, "email") => "Email",
+        (Language::French, "job_title") => "Fonction",
+        (Language::French, "department") => "Département",
+        (Language::French, "office") => "Bureau",
+        (Language::French, "manager") => "Responsable",
+        (Language::French, "hire_date") => "Date d'engagement",
+        (Language::French, "notes") => "Notes",
+        (Language::French, "role") => "Rôle",
+        (Language::French, "code") => "Code d'invitation",
+        (Language::French, "session") => "Session",
+        (_, other) => other,
+    };
+    name.to_string()
+}
+
+/**
+Parameter: reason - reason for which an input was refused
+Return: String - Message to display
+ **/
+pub fn validation_error(reason: &ValidationError) -> String {
+    let mut message = match (language(), reason) {
+        (Language::English, ValidationError::InvalidUsername) => "wrong format",
+        (Language::English, ValidationError::ReservedUsername) => "this username is reserved",
+        (Language::English, ValidationError::InvalidPhone) => "wrong format",
+        (Language::English, ValidationError::InvalidPassword) => "wrong format",
+        (Language::English, ValidationError::PasswordRejected(_)) => "it doesn't follow the password policy",
+        (Language::English, ValidationError::InvalidQuery) => "too long or contains invalid characters",
+        (Language::English, ValidationError::InvalidPageSize) => "it must be between 1 and 50",
+        (Language::English, ValidationError::InvalidCursor) => "it doesn't exist, please search again",
+        (Language::English, ValidationError::InvalidEmail) => "wrong format",
+        (Language::English, ValidationError::InvalidText) => "too long or contains invalid characters",
+        (Language::English, ValidationError::InvalidDate) => "it must be a past date in the YYYY-MM-DD format",
+        (Language::English, ValidationError::InvalidManager) => "a user can't be managed by themselves or by someone who reports to them",
+        (Language::English, ValidationError::NotEditable) => "you can't change this field, please ask HR",
+        (Language::English, ValidationError::InvalidInviteCode) => "it must have 32 hexadecimal digits",
+        (Language::English, ValidationError::CurrentSession) => "use Logout or Exit to end the current session",
+        (Language::French, ValidationError::InvalidUsername) => "format incorrect",
+        (Language::French, ValidationError::ReservedUsername) => "ce nom d'utilisateur est réservé",
+        (Language::French, ValidationError::InvalidPhone) => "format incorrect",
+        (Language::French, ValidationError::InvalidPassword) => "format incorrect",
+        (Language::French, ValidationError::PasswordRejected(_)) => "il ne respecte pas la politique des mots de passe",
+        (Language::French, ValidationError::InvalidQuery) => "trop longue ou contient des caractères invalides",
+        (Language::French, ValidationError::InvalidPageSize) => "elle doit être entre 1 et 50",
+        (Language::French, ValidationError::InvalidCursor) => "elle n'existe pas, veuillez relancer la recherche",
+        (Language::French, ValidationError::InvalidEmail) => "format incorrect",
+        (Language::French, ValidationError::InvalidText) => "trop long ou contient des caractères invalides",
+        (Language::French, ValidationError::InvalidDate) => "ce doit être une date passée au format AAAA-MM-JJ",
+        (Language::French, ValidationError::InvalidManager) => "un utilisateur ne peut pas être géré par lui-même ou par une personne qui dépend de lui",
+        (Language::French, ValidationError::NotEditable) => "vous ne pouvez pas modifier ce champ, veuillez contacter les RH",
+        (Language::French, ValidationError::InvalidInviteCode) => "il doit contenir 32 chiffres hexadécimaux",
+        (Language::French, ValidationError::CurrentSession) => "utilisez Logout ou Exit pour fermer la session actuelle",
+    }
+    .to_string();
+
+    if let ValidationError::PasswordRejected(rejections) = reason {
+        for rejection in rejections {
+            message.push_str(&format!("\n\t- {}", password_rejection(rejection)));
+        }
+    }
+    message
+}
+
+/**
+Parameter: rejection - rule of the password policy that isn't met
+Return: String - Message to display
+ **/
+pub fn password_rejection(rejection: &PasswordRejection) -> String {
+    match (language(), rejection) {
+        (Language::English, PasswordRejection::TooShort { min }) => format!("It must be at least {} characters long", min),
+        (Language::English, PasswordRejection::TooLong { max }) => format!("It must be at most {} characters long", max),
+        (Language::English, PasswordRejection::ContainsBannedWord(word)) => format!("It must not contain \"{}\"", word),
+        (Language::English, PasswordRejection::Breached) => "It appears in a list of breached passwords".to_string(),
+        (Language::English, PasswordRejection::Reused) => "It was already used recently".to_string(),
+        (Language::French, PasswordRejection::TooShort { min }) => format!("Il doit contenir au moins {} caractères", min),
+        (Language::French, PasswordRejection::TooLong { max }) => format!("Il doit contenir au plus {} caractères", max),
+        (Language::French, PasswordRejection::ContainsBannedWord(word)) => format!("Il ne doit pas contenir « {} »", word),
+        (Language::French, PasswordRejection::Breached) => "Il apparaît dans une liste de mots de passe divulgués".to_string(),
+        (Language::French, PasswordRejection::Reused) => "Il a déjà été utilisé récemment".to_string(),
+        // The warning and suggestions come from zxcvbn, which only provides them in English
+        (lang, PasswordRejection::TooWeak { warning, suggestions }) => {
+            let (weak, suggestion) = match lang {
+                Language::English => ("It is too easy to guess", "Suggestion"),
+                Language::French => ("Il est trop facile à deviner", "Suggestion"),
+            };
+            let mut message = weak.to_string();
+            if let Some(warning) = warning {
+                message.push_str(&format!(": {}", warning));
+            }
+            for s in suggestions {
+                message.push_str(&format!("\n\t  {}: {}", suggestion, s));
+            }
+            message
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use std::error::Error;
 use casbin::CoreApi;
 use casbin::prelude::Enforcer;
-use crate::{Action, ConnectedUser, UserRole};
+use crate::{ConnectedUser, UserRole};
 use log::warn;
 
+/**
+Parameters: u   - connected user performing the action
+            obj - object of the action in the casbin policy
+Return: Result<bool, Box<dyn Error>> - True if the role of the user is allowed to access the object
+ **/
 #[tokio::main]
-pub async fn verify_action(u: &mut ConnectedUser, action: &Action) -> Result<bool, Box<dyn Error>> {
-    let mut e = Enforcer::new("access/access.conf", "access/access.csv")
-        .await
-        .expect("Cannot read model or policy");
+pub async fn verify_action(u: &mut ConnectedUser, obj: &str) -> Result<bool, Box<dyn Error>> {
+    let mut e = Enforcer::new("access/access.conf", "access/access.csv").await?;
     e.enable_log(true);
 
     let sub = if u.is_anonymous() {
@@ -20,30 +23,15 @@ pub async fn verify_action(u: &mut ConnectedUser, action: &Action) -> Result<boo
         }
     };
 
-    let obj = match action {
-        Action::ShowUsers => "show_users",
-        Action::ChangeOwnPhone => "change_own_phone",
-        Action::ChangePhone => "change_phone",
-        Action::AddUser => "add_user",
-        Action::Login => "login",
-        Action::Logout => "logout",
-        Action::Exit => "exit",
-    };
-
     if let Ok(authorized) = e.enforce((sub, obj)) {
         if authorized {
             Ok(true)
         } else {
-            let user = if u.is_anonymous() {
-                "An anonymous user".to_string()
-            } else {
-                u.username()
-            };
-            warn!("{} tried to access a non-authorized action: {:?}", user, action);
+            warn!("Non-authorized access to {} by {}", obj, u.display_name());
             Ok(false)
         }
     } else {
         warn!("Error with the access verification");
         Err("Error with the access verification".into())
     }
-}
\ No newline at end of file
+}
@@ -4,246 +4,624 @@
 ///             - Input/output validation
 ///             - Log stuff whenever required
 ///             - Potential improvements
+use crate::access::verify_action;
+use crate::approval::PendingView;
+use crate::config::CONFIG;
 use crate::connection::Connection;
-use crate::crypto::{generate_hash, generate_salt, verify_hash};
 use crate::database::Database;
-use crate::user::{UserAccount, UserRole};
-use crate::validate_inputs::{validate_password, validate_phone, validate_username};
-use log::{info, warn};
+use crate::handlers;
+use crate::session::{self, SessionView};
+use crate::user::{FieldChange, ProfileField, ProfileView, UserAccount, UserRole, UserView, Viewer};
+use crate::validate_inputs::{Username, ValidationError};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
-use strum_macros::{EnumIter, EnumString};
-use crate::access::verify_action;
+use std::fmt;
+use std::net::IpAddr;
 
-#[derive(Serialize, Deserialize, Debug, EnumString, EnumIter)]
+/// Request sent by the client, each action carries all of its inputs
+#[derive(Serialize, Deserialize, Debug)]
 pub enum Action {
-    #[strum(serialize = "Show users", serialize = "1")]
     ShowUsers,
-    #[strum(serialize = "Change my phone number", serialize = "2")]
-    ChangeOwnPhone,
-    #[strum(serialize = "Show someone's phone number", serialize = "3")]
-    ChangePhone,
-    #[strum(serialize = "Add user", serialize = "4")]
-    AddUser,
-    #[strum(serialize = "Login", serialize = "5")]
-    Login,
-    #[strum(serialize = "Logout", serialize = "6")]
+    UpdateOwnProfile(UpdateOwnProfileRequest),
+    UpdateProfile(UpdateProfileRequest),
+    AddUser(AddUserRequest),
+    Login(LoginRequest),
     Logout,
-    #[strum(serialize = "Exit", serialize = "7")]
+    ChangePassword(ChangePasswordRequest),
+    SearchUsers(SearchUsersRequest),
+    ViewProfile(ViewProfileRequest),
+    ChainOfCommand(ChainOfCommandRequest),
+    DirectReports(DirectReportsRequest),
+    OrgChart,
+    ViewHistory(ViewHistoryRequest),
+    RevertField(RevertFieldRequest),
+    ChangeRole(ChangeRoleRequest),
+    DeleteUser(DeleteUserRequest),
+    ListPending,
+    Approve(PendingDecisionRequest),
+    Reject(PendingDecisionRequest),
+    Register(RegisterRequest),
+    CreateInvite(CreateInviteRequest),
+    RedeemInvite(RedeemInviteRequest),
+    ListMySessions,
+    ListSessions,
+    TerminateSession(TerminateSessionRequest),
     Exit,
 }
 
-/// The individual actions are implemented with three main steps:
-///     1. Read client inputs if required
-///     2. Execute various server code
-///     3. Send a result
-impl Action {
-    pub fn perform(&self, u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        let res = match self {
-            Action::ShowUsers => Action::show_users(u),
-            Action::ChangeOwnPhone => Action::change_own_phone(u),
-            Action::ChangePhone => Action::change_phone(u),
-            Action::AddUser => Action::add_user(u),
-            Action::Login => Action::login(u),
-            Action::Logout => Action::logout(u),
-            Action::Exit => Err("Client disconnected")?,
-        };
+/// New values of profile fields, an empty value clears an optional field
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateOwnProfileRequest {
+    pub changes: Vec<(ProfileField, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateProfileRequest {
+    pub username: String,
+    pub changes: Vec<(ProfileField, String)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ViewProfileRequest {
+    /// None for the profile of the connected user
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChainOfCommandRequest {
+    /// None for the managers of the connected user
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DirectReportsRequest {
+    /// None for the reports of the connected user
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ViewHistoryRequest {
+    pub username: String,
+}
+
+/// Gives back to a field the value it had before a change of the history
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevertFieldRequest {
+    pub username: String,
+    pub version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddUserRequest {
+    pub username: String,
+    pub password: String,
+    pub phone_number: String,
+    pub role: UserRole,
+}
+
+/// Account asked by an anonymous user, always a standard user
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegisterRequest {
+    pub username: String,
+    pub password: String,
+    pub phone_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateInviteRequest {
+    pub username: String,
+    pub role: UserRole,
+}
+
+/// Account created from an invitation, the new user chooses the password
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RedeemInviteRequest {
+    pub code: String,
+    pub password: String,
+    pub phone_number: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TerminateSessionRequest {
+    pub id: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangeRoleRequest {
+    pub username: String,
+    pub role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteUserRequest {
+    pub username: String,
+}
+
+/// Approval or rejection of a pending request
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PendingDecisionRequest {
+    pub id: u32,
+}
 
-        res
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+/// How the query of a search is matched against the usernames
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum MatchMode {
+    Prefix,
+    Substring,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    Username,
+    /// By role, then by username
+    Role,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchUsersRequest {
+    pub query: String,
+    pub mode: MatchMode,
+    pub role: Option<UserRole>,
+    pub sort: SortField,
+    pub descending: bool,
+    /// Cursor returned with the previous page, None for the first page
+    pub cursor: Option<String>,
+    pub page_size: u32,
+}
+
+/// Value sent to the client when an action succeeds
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    Done,
+    Users(Vec<UserView>),
+    /// Page of search results, with the cursor of the next page if there is one
+    UserPage {
+        users: Vec<UserView>,
+        next_cursor: Option<String>,
+    },
+    Profile(ProfileView),
+    /// Org chart in the DOT format of Graphviz
+    OrgChart(String),
+    /// Changes of the profile of an account, the oldest first
+    History(Vec<FieldChange>),
+    /// The operation waits for the approval of HR, with the number of the request
+    PendingApproval(u32),
+    Pending(Vec<PendingView>),
+    /// Code to give to the invited user, only sent once
+    Invitation { code: String, expires_at: DateTime<Utc> },
+    Sessions(Vec<SessionView>),
+}
+
+impl From<()> for Response {
+    fn from(_: ()) -> Self {
+        Response::Done
+    }
+}
+
+impl From<Vec<UserView>> for Response {
+    fn from(users: Vec<UserView>) -> Self {
+        Response::Users(users)
     }
+}
 
-    pub fn show_users(u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        // Check permissions
-        let res = match verify_action(u, &Action::ShowUsers) {
-            Ok(true) => {
-                let users = Database::values()?;
-                info!("Users sent");
-                Ok(users)
-            },
-            _ => Err("You can't do this action"),
-        };
+/// Error sent to the client when an action fails, the client chooses the message to display
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ActionError {
+    /// The user isn't allowed to perform the action or gave wrong credentials
+    Unauthorized,
+    /// An input was refused before executing the action
+    ValidationFailed { field: String, reason: ValidationError },
+    /// The target of the action doesn't exist
+    NotFound,
+    /// The action conflicts with the existing data, e.g. a username already taken
+    Conflict,
+    /// Too many requests, the client can retry after this number of seconds
+    RateLimited { retry_after: u64 },
+    /// The server failed, the details are only logged
+    Internal,
+    /// The user was logged out after being idle or logged in for too long, the action wasn't performed
+    SessionExpired,
+    /// The password of the user has expired, it must be changed before any other action
+    PasswordExpired,
+}
 
-        u.conn().send(&res)
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::Unauthorized => write!(f, "Unauthorized"),
+            ActionError::ValidationFailed { field, reason } => write!(f, "Invalid {}: {}", field, reason),
+            ActionError::NotFound => write!(f, "Not found"),
+            ActionError::Conflict => write!(f, "Conflict"),
+            ActionError::RateLimited { retry_after } => write!(f, "Rate limited for {}s", retry_after),
+            ActionError::Internal => write!(f, "Internal error"),
+            ActionError::SessionExpired => write!(f, "Session expired"),
+            ActionError::PasswordExpired => write!(f, "Password expired"),
+        }
     }
+}
 
-    pub fn change_own_phone(u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        let phone = u.conn().receive::<String>()?;
+impl Error for ActionError {}
 
-        // Check permissions
-        let res = match verify_action(u, &Action::ChangeOwnPhone) {
-            Ok(true) => {
-                if !validate_phone(&phone) {
-                    warn!("Invalid phone format from user {}", u.username());
-                    Err("Invalid phone format")
-                } else {
-                    let mut user = u.user_account()?;
-                    user.set_phone_number(phone.clone());
-                    Database::insert(&user)?;
-                    info!("Phone number changed for user {}", u.username());
-                    Ok(())
-                }
-            },
-            _ => Err("You can't do this action"),
-        };
+impl From<Box<dyn Error>> for ActionError {
+    /// Failures of the server, e.g. of the database, are logged and only reported as internal errors
+    fn from(e: Box<dyn Error>) -> Self {
+        error!("Internal error: {}", e);
+        ActionError::Internal
+    }
+}
 
-        u.conn().send(&res)
-    }
-
-    pub fn change_phone(u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        // Receive data
-        let username = u.conn().receive::<String>()?;
-        let phone = u.conn().receive::<String>()?;
-        let target_user = Database::get(&username)?;
-
-        // Check permissions
-        let res = match verify_action(u, &Action::ChangePhone) {
-            Ok(true) => {
-                if !validate_username(&username) {
-                    warn!("Invalid username format from user {}", u.username());
-                    Err("Invalid username format")
-                } else if !validate_phone(&phone) {
-                    warn!("Invalid phone format from user {}", u.username());
-                    Err("Invalid phone format")
-                }else if target_user.is_none() {
-                    warn!("Target user not found from user {}", u.username());
-                    Err("Target user not found")
-                } else {
-                    let mut target_user = target_user.unwrap();
-                    target_user.set_phone_number(phone);
-                    Database::insert(&target_user)?;
-                    info!("Phone number changed for user {} from user {}", username, u.username());
-                    Ok(())
-                }
-            },
-            _ => Err("You can't do this action"),
-        };
+/// Names the input refused by a validation, so that the client can point to it
+pub trait Field<T> {
+    fn field(self, name: &str) -> Result<T, ActionError>;
+}
 
-        u.conn().send(&res)
-    }
-
-    pub fn add_user(u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        // Receive data
-        let username = u.conn().receive::<String>()?;
-        let password = u.conn().receive::<String>()?;
-        let phone = u.conn().receive::<String>()?;
-        let role = u.conn().receive::<UserRole>()?;
-
-        // Check permissions
-        let res = match verify_action(u, &Action::AddUser) {
-            Ok(true) => {
-                if !validate_username(&username) {
-                    warn!("Invalid username format from user {}", u.username());
-                    Err("Invalid username format")
-                }else if !validate_password(&password) {
-                    warn!("Invalid password format from user {}", u.username());
-                    Err("Invalid password format")
-                }else if !validate_phone(&phone) {
-                    warn!("Invalid phone format from user {}", u.username());
-                    Err("Invalid phone format")
-                }else if Database::get(&username)?.is_some() {
-                    warn!("User already exists ({}) from user {}", username, u.username());
-                    Err("User already exists")
-                } else {
-                    let salt = generate_salt();
-                    let hash_password = generate_hash(&password, &salt);
-                    let user = UserAccount::new(username, hash_password, phone, role);
-                    info!("User added in database from user {}", u.username());
-                    Ok(Database::insert(&user)?)
-                }
-            },
-            _ => Err("You can't do this action"),
-        };
+impl<T> Field<T> for Result<T, ValidationError> {
+    fn field(self, name: &str) -> Result<T, ActionError> {
+        self.map_err(|reason| ActionError::ValidationFailed {
+            field: name.to_string(),
+            reason,
+        })
+    }
+}
 
-        u.conn.send(&res)
-    }
-
-    pub fn login(u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        // Receive data
-        let username = u.conn().receive::<String>()?;
-        let password = u.conn().receive::<String>()?;
-
-        // Check permissions
-        let res = match verify_action(u, &Action::Login) {
-            Ok(true) => {
-                if !validate_username(&username) {
-                    warn!("Invalid username format");
-                    Err("Invalid username format")
-                } else if !validate_password(&password) {
-                    warn!("Invalid password format");
-                    Err("Invalid password format")
-                } else {
-                    let user = Database::get(&username)?;
-                    if let Some(user) = user {
-                        if verify_hash(&user.password().to_string(), &password) {
-                            u.set_username(&username);
-                            info!("{} has logged in", u.username());
-                            Ok(())
-                        } else {
-                            warn!("Invalid inputs for username : {}", username);
-                            Err("Invalid inputs")
-                        }
-                    } else {
-                        warn!("Invalid inputs for username : {}", username);
-                        Err("Invalid inputs")
-                    }
-                }
-            },
-            _ => Err("You can't do this action"),
-        };
+/// Result of an action, as sent to the client
+pub type ActionResult = Result<Response, ActionError>;
+
+/// Implemented by every action, `dispatch` takes care of the authorisation, the logging and of
+/// sending the result, so that an action only has to validate its request and execute itself
+pub trait ActionHandler {
+    /// Object of the action in the casbin policy (access/access.csv)
+    const OBJECT: &'static str;
+    /// Whether the action stays available to a user whose password has expired
+    const ALLOWED_WITH_EXPIRED_PASSWORD: bool = false;
 
-        u.conn.send(&res)
+    /// Inputs sent by the client
+    type Request;
+    /// Inputs once validated
+    type Input;
+    /// Value sent to the client when the action succeeds
+    type Output: Into<Response>;
+
+    /**
+    Parameters: request - inputs sent by the client
+                u       - connected user, the database must not be accessed during the validation
+    Return: Result<Input, ActionError> - Validated inputs
+     **/
+    fn validate(request: Self::Request, u: &ConnectedUser) -> Result<Self::Input, ActionError>;
+
+    /**
+    Parameters: input - validated inputs
+                u     - connected user performing the action
+    Return: Result<Output, ActionError> - Result sent to the client, the session goes on after an error
+     **/
+    fn execute(input: Self::Input, u: &mut ConnectedUser) -> Result<Self::Output, ActionError>;
+}
+
+impl Action {
+    pub fn perform(self, u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
+        // A login or an exit still goes on, the user was about to authenticate again or to leave anyway
+        if u.expire_session() && !matches!(self, Action::Login(_) | Action::Exit) {
+            let res: ActionResult = Err(ActionError::SessionExpired);
+            return u.conn().send(&res);
+        }
+        u.record_activity();
+
+        match self {
+            Action::ShowUsers => dispatch::<handlers::ShowUsers>(u, ()),
+            Action::UpdateOwnProfile(request) => dispatch::<handlers::UpdateOwnProfile>(u, request),
+            Action::UpdateProfile(request) => dispatch::<handlers::UpdateProfile>(u, request),
+            Action::AddUser(request) => dispatch::<handlers::AddUser>(u, request),
+            Action::Login(request) => dispatch::<handlers::Login>(u, request),
+            Action::Logout => dispatch::<handlers::Logout>(u, ()),
+            Action::ChangePassword(request) => dispatch::<handlers::ChangePassword>(u, request),
+            Action::SearchUsers(request) => dispatch::<handlers::SearchUsers>(u, request),
+            Action::ViewProfile(request) => dispatch::<handlers::ViewProfile>(u, request),
+            Action::ChainOfCommand(request) => dispatch::<handlers::ChainOfCommand>(u, request),
+            Action::DirectReports(request) => dispatch::<handlers::DirectReports>(u, request),
+            Action::OrgChart => dispatch::<handlers::OrgChart>(u, ()),
+            Action::ViewHistory(request) => dispatch::<handlers::ViewHistory>(u, request),
+            Action::RevertField(request) => dispatch::<handlers::RevertField>(u, request),
+            Action::ChangeRole(request) => dispatch::<handlers::ChangeRole>(u, request),
+            Action::DeleteUser(request) => dispatch::<handlers::DeleteUser>(u, request),
+            Action::ListPending => dispatch::<handlers::ListPending>(u, ()),
+            Action::Approve(request) => dispatch::<handlers::Approve>(u, request),
+            Action::Reject(request) => dispatch::<handlers::Reject>(u, request),
+            Action::Register(request) => dispatch::<handlers::Register>(u, request),
+            Action::CreateInvite(request) => dispatch::<handlers::CreateInvite>(u, request),
+            Action::RedeemInvite(request) => dispatch::<handlers::RedeemInvite>(u, request),
+            Action::ListMySessions => dispatch::<handlers::ListMySessions>(u, ()),
+            Action::ListSessions => dispatch::<handlers::ListSessions>(u, ()),
+            Action::TerminateSession(request) => dispatch::<handlers::TerminateSession>(u, request),
+            Action::Exit => Err("Client disconnected")?,
+        }
     }
+}
 
-    pub fn logout(u: &mut ConnectedUser) -> Result<(), Box<dyn Error>> {
-        // Check permissions
-        let res = match verify_action(u, &Action::Logout) {
-            Ok(true) => {
-                info!("{} has logged out", u.username());
-                u.logout();
-                Ok(())
-            },
-            _ => Err("You can't do this action"),
-        };
+/**
+Parameters: u       - connected user performing the action
+            request - inputs sent by the client
+Return: Result<(), Box<dyn Error>> - Runs the action and sends its result, an error means that the
+        connection failed and must be closed.
+        Every action goes through the same steps in this order, so that an unauthorised user or
+        invalid inputs never reach the database:
+            1. Authorise the connected user
+            2. Validate the inputs
+            3. Execute the action
+ **/
+pub fn dispatch<H: ActionHandler>(u: &mut ConnectedUser, request: H::Request) -> Result<(), Box<dyn Error>> {
+    let user = u.display_name();
+
+    let res: ActionResult = match authorise::<H>(u) {
+        Err(e) => Err(e),
+        Ok(()) => match H::validate(request, u) {
+            Ok(input) => H::execute(input, u).map(Into::into),
+            Err(e) => Err(e),
+        },
+    };
+
+    // Audit log of every action
+    match &res {
+        Ok(_) => info!("Action {} performed by {}", H::OBJECT, user),
+        Err(e) => warn!("Action {} refused to {}: {}", H::OBJECT, user, e),
+    }
+
+    u.conn().send(&res)
+}
+
+/**
+Parameter: u - connected user performing the action
+Return: Result<(), ActionError> - Ok if the user is allowed to perform the action
+ **/
+fn authorise<H: ActionHandler>(u: &mut ConnectedUser) -> Result<(), ActionError> {
+    // The password may have expired since the login
+    if !u.is_anonymous() && !u.password_expired() {
+        let user = u.user_account()?;
+        u.check_password_expiry(&user);
+    }
+    if u.password_expired() && !H::ALLOWED_WITH_EXPIRED_PASSWORD {
+        warn!("Access to {} with an expired password by {}", H::OBJECT, u.display_name());
+        return Err(ActionError::PasswordExpired);
+    }
 
-        u.conn.send(&res)
+    match verify_action(u, H::OBJECT) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ActionError::Unauthorized),
+        Err(e) => match e.downcast::<ActionError>() {
+            // Reading the account already decided, e.g. it was deleted during the session
+            Ok(e) => Err(*e),
+            Err(e) => {
+                error!("Cannot verify the access to {}: {}", H::OBJECT, e);
+                Err(ActionError::Internal)
+            }
+        },
     }
 }
 
+/**
+Parameter: user - account to check
+Return: Option<DateTime<Utc>> - Date at which the password expires, None if it never does
+ **/
+pub fn password_expiry(user: &UserAccount) -> Option<DateTime<Utc>> {
+    user.password_expiry(CONFIG.password_policy.max_age_days.map(Duration::days))
+}
+
 /// Used to represent a connected user for the actions
 pub struct ConnectedUser {
-    username: Option<String>,
+    username: Option<Username>,
+    password_expired: bool,
+    /// Number of the session in the registry, which keeps the login time and the last activity
+    session_id: u64,
     conn: Connection,
 }
 
 impl ConnectedUser {
+    /// Anonymous user, the connection is added to the session registry until it is closed
     pub fn anonymous(conn: Connection) -> ConnectedUser {
         ConnectedUser {
             username: None,
+            password_expired: false,
+            session_id: session::open(conn.peer_ip(), conn.stream_handle()),
             conn,
         }
     }
 
-    pub fn username(&mut self) -> String {
-        self.username.as_ref().unwrap().clone()
+    /// Number of the session in the registry
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Username of the session, None while anonymous
+    pub fn account_name(&self) -> Option<&Username> {
+        self.username.as_ref()
+    }
+
+    /// Name used in the logs, also for anonymous users
+    pub fn display_name(&self) -> String {
+        match &self.username {
+            Some(username) => format!("user {}", username),
+            None => "an anonymous user".to_string(),
+        }
+    }
+
+    /// Address of the client, None if it couldn't be read
+    pub fn peer_ip(&self) -> Option<IpAddr> {
+        self.conn.peer_ip()
     }
 
     pub fn conn(&mut self) -> &mut Connection {
         &mut self.conn
     }
 
-    pub fn set_username(&mut self, username: &str) {
-        self.username = Some(username.to_string());
+    pub fn set_username(&mut self, username: &Username) {
+        self.username = Some(username.clone());
+        session::set_username(self.session_id, Some(username));
     }
 
     pub fn is_anonymous(&self) -> bool {
-        return self.username.is_none();
+        self.username.is_none()
+    }
+
+    /// A user with an expired password can only change it or log out
+    pub fn password_expired(&self) -> bool {
+        self.password_expired
+    }
+
+    /**
+    Parameter: user - account of the connected user
+    Return: None - The password is marked as expired if its maximum age has been reached
+     **/
+    pub fn check_password_expiry(&mut self, user: &UserAccount) {
+        if !self.password_expired && password_expiry(user).is_some_and(|expiry| expiry <= Utc::now()) {
+            warn!("The password of {} has expired during the session", user.username());
+            self.password_expired = true;
+        }
+    }
+
+    pub fn set_password_expired(&mut self, expired: bool) {
+        self.password_expired = expired;
     }
 
     pub fn logout(&mut self) {
         self.username = None;
+        self.password_expired = false;
+        session::set_username(self.session_id, None);
+    }
+
+    /// Called for each action received, also by anonymous users
+    pub fn record_activity(&mut self) {
+        session::record_activity(self.session_id);
+    }
+
+    /**
+    Parameter: None
+    Return: Bool - True if the user was logged in but has been idle or logged in for too long,
+            the user is then logged out
+     **/
+    pub fn expire_session(&mut self) -> bool {
+        if self.is_anonymous() || session::is_logged_in(self.session_id) {
+            return false;
+        }
+        self.logout();
+        true
+    }
+
+    /**
+    Parameter: None
+    Return: Result<Viewer, ActionError> - Who the connected user is when looking at the directory
+     **/
+    pub fn viewer(&mut self) -> Result<Viewer, ActionError> {
+        if self.is_anonymous() {
+            return Ok(Viewer::Anonymous);
+        }
+        Ok(match self.user_account()?.role() {
+            UserRole::StandardUser => Viewer::StandardUser,
+            UserRole::HR => Viewer::HR,
+        })
+    }
+
+    /**
+    Parameter: None
+    Return: Result<UserAccount, ActionError> - Account of the connected user, the session is closed
+            if the account doesn't exist anymore
+     **/
+    pub fn user_account(&mut self) -> Result<UserAccount, ActionError> {
+        let username = self.username.clone().ok_or(ActionError::Unauthorized)?;
+        match Database::get(&username)? {
+            Some(user) => Ok(user),
+            None => {
+                warn!("Account {} doesn't exist anymore, its session is closed", username);
+                self.logout();
+                Err(ActionError::Unauthorized)
+            }
+        }
+    }
+}
+
+impl Drop for ConnectedUser {
+    fn drop(&mut self) {
+        session::close(self.session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_tls::{TlsConnector, TlsStream};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // Handlers that fail the test if an anonymous user gets past the authorisation, before the
+    // inputs are validated or the database is accessed
+    macro_rules! unreachable_handler {
+        ($name:ident, $object:literal) => {
+            struct $name;
+
+            impl ActionHandler for $name {
+                const OBJECT: &'static str = $object;
+
+                type Request = ();
+                type Input = ();
+                type Output = ();
+
+                fn validate(_: (), _: &ConnectedUser) -> Result<(), ActionError> {
+                    panic!("{} was validated for an anonymous user", Self::OBJECT)
+                }
+
+                fn execute(_: (), _: &mut ConnectedUser) -> Result<(), ActionError> {
+                    panic!("{} was executed for an anonymous user", Self::OBJECT)
+                }
+            }
+        };
+    }
+
+    unreachable_handler!(AdminAction, "update_profile");
+    unreachable_handler!(IdentifiedAction, "update_own_profile");
+
+    // Anonymous user connected through TLS on the loopback, with the client end of the connection
+    fn anonymous_user() -> (ConnectedUser, TlsStream<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let connector = TlsConnector::builder().danger_accept_invalid_certs(true).build().unwrap();
+            connector.connect("localhost", TcpStream::connect(addr).unwrap()).unwrap()
+        });
+        let acceptor = crate::tls_config(crate::CERT_PATH, crate::KEY_PATH);
+        let (stream, _) = listener.accept().unwrap();
+        let conn = Connection::new(acceptor.accept(stream).unwrap());
+        (ConnectedUser::anonymous(conn), client.join().unwrap())
+    }
+
+    fn assert_refused<H: ActionHandler<Request = ()>>() {
+        let (mut u, mut client) = anonymous_user();
+        assert!(matches!(authorise::<H>(&mut u), Err(ActionError::Unauthorized)));
+
+        dispatch::<H>(&mut u, ()).unwrap();
+        let res: ActionResult = bincode::deserialize_from(&mut client).unwrap();
+        assert!(matches!(res, Err(ActionError::Unauthorized)));
+        assert!(u.is_anonymous());
+    }
+
+    #[test]
+    fn anonymous_user_cannot_perform_admin_actions() {
+        assert_refused::<AdminAction>();
     }
 
-    pub fn user_account(&mut self) -> Result<UserAccount, Box<dyn Error>> {
-        Ok(Database::get(&self.username())?.expect("User logged in but not in DB"))
+    #[test]
+    fn anonymous_user_cannot_perform_identified_actions() {
+        assert_refused::<IdentifiedAction>();
     }
 }
@@ -0,0 +1,128 @@
+/// This file is used to implement the four-eyes workflow: sensitive operations are only stored as
+/// pending requests, and are applied once a second HR user approves them. Self-registrations wait
+/// in the same queue for the approval of HR
+use crate::user::{UserAccount, UserRole};
+use crate::validate_inputs::{username_key, Username};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Operation waiting for an approval
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Operation {
+    /// Account ready to be inserted, its password is already hashed
+    AddUser(Box<UserAccount>),
+    ChangeRole(Username, UserRole),
+    DeleteUser(Username),
+    /// Account registered by an anonymous user, it can't log in before being approved
+    Register(Box<UserAccount>),
+}
+
+impl Operation {
+    /// Account targeted by the operation
+    pub fn username(&self) -> &Username {
+        match self {
+            Operation::AddUser(user) | Operation::Register(user) => user.username(),
+            Operation::ChangeRole(username, _) | Operation::DeleteUser(username) => username,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingRequest {
+    pub id: u32,
+    pub operation: Operation,
+    /// None for a self-registration
+    pub requested_by: Option<Username>,
+    pub requested_at: DateTime<Utc>,
+}
+
+impl PendingRequest {
+    /// Whether the user asked for the request, they can't approve it
+    pub fn is_requested_by(&self, username: &Username) -> bool {
+        self.requested_by.as_ref().is_some_and(|author| username_key(author) == username_key(username))
+    }
+
+    /// Whether the user is the account targeted, they can neither approve nor reject the request
+    pub fn targets(&self, username: &Username) -> bool {
+        username_key(self.operation.username()) == username_key(username)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum OperationKind {
+    AddUser,
+    ChangeRole,
+    DeleteUser,
+    Register,
+}
+
+/// Pending request as sent to the clients, without the password hash of a new account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingView {
+    id: u32,
+    kind: OperationKind,
+    username: Username,
+    /// Role of the new account, or new role of the account
+    role: Option<UserRole>,
+    requested_by: Option<Username>,
+    requested_at: DateTime<Utc>,
+}
+
+impl PendingView {
+    pub fn new(request: &PendingRequest) -> PendingView {
+        let (kind, role) = match &request.operation {
+            Operation::AddUser(user) => (OperationKind::AddUser, Some(*user.role())),
+            Operation::ChangeRole(_, role) => (OperationKind::ChangeRole, Some(*role)),
+            Operation::DeleteUser(_) => (OperationKind::DeleteUser, None),
+            Operation::Register(user) => (OperationKind::Register, Some(*user.role())),
+        };
+        PendingView {
+            id: request.id,
+            kind,
+            username: request.operation.username().clone(),
+            role,
+            requested_by: request.requested_by.clone(),
+            requested_at: request.requested_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn username(name: &str) -> Username {
+        Username::parse(name).unwrap()
+    }
+
+    fn request(operation: Operation, requested_by: &str) -> PendingRequest {
+        PendingRequest {
+            id: 1,
+            operation,
+            requested_by: Some(username(requested_by)),
+            requested_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn target_of_a_deletion_is_recognised() {
+        let request = request(Operation::DeleteUser(username("default_hr2")), "default_hr");
+        assert!(request.targets(&username("Default_HR2")));
+        assert!(!request.targets(&username("default_hr")));
+        assert!(request.is_requested_by(&username("DEFAULT_HR")));
+        assert!(!request.is_requested_by(&username("default_hr2")));
+    }
+
+    #[test]
+    fn target_of_a_role_change_is_recognised() {
+        let request = request(Operation::ChangeRole(username("default_hr2"), UserRole::StandardUser), "default_hr");
+        assert!(request.targets(&username("default_hr2")));
+        assert!(!request.targets(&username("someone_else")));
+    }
+
+    #[test]
+    fn registration_has_no_author() {
+        let request = PendingRequest { requested_by: None, ..request(Operation::DeleteUser(username("alice")), "bob") };
+        assert!(!request.is_requested_by(&username("bob")));
+    }
+}
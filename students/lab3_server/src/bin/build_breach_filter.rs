@@ -0,0 +1,53 @@
+/// This tool builds the breached passwords filter used by the server from a password list
+/// containing one password per line.
+///
+/// Usage: build_breach_filter <password_list> <output_filter> [false_positive_rate]
+#[allow(dead_code)] // The lookup is only done by the server
+#[path = "../breach.rs"]
+mod breach;
+
+use crate::breach::BloomFilter;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process;
+
+const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.001;
+
+fn read_passwords(path: &str) -> Result<impl Iterator<Item = String>, Box<dyn Error>> {
+    Ok(BufReader::new(File::open(path)?)
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .filter(|line| !line.is_empty()))
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("Usage: {} <password_list> <output_filter> [false_positive_rate]", args[0]);
+        // Usage errors exit with 2, like most command line tools
+        process::exit(2);
+    }
+    let false_positive_rate: f64 = match args.get(3) {
+        Some(rate) => rate.parse()?,
+        None => DEFAULT_FALSE_POSITIVE_RATE,
+    };
+    // 0 would need an infinite filter, 1 or more a filter that finds every password
+    if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+        eprintln!("The false positive rate must be between 0 and 1, e.g. {}", DEFAULT_FALSE_POSITIVE_RATE);
+        process::exit(2);
+    }
+
+    // The list is read twice to size the filter without keeping it in memory
+    let count = read_passwords(&args[1])?.count();
+    let mut filter = BloomFilter::new(count, false_positive_rate);
+    for password in read_passwords(&args[1])? {
+        filter.insert(&password);
+    }
+
+    filter.save(&args[2])?;
+    println!("{} passwords written to {}", count, args[2]);
+    Ok(())
+}
@@ -0,0 +1,125 @@
+/// This file is used to check passwords against a list of breached passwords
+///
+/// The list is stored as a bloom filter built by the `build_breach_filter` tool, so the
+/// passwords themselves never need to be on the server
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+#[derive(Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+// Building the filter is only done by the build_breach_filter tool, the server only reads it
+#[allow(dead_code)]
+impl BloomFilter {
+    /**
+    Parameters: expected_items      - number of passwords that will be inserted
+                false_positive_rate - wanted probability that an unknown password is reported
+    Return: BloomFilter - Empty filter sized for these parameters
+     **/
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> BloomFilter {
+        let n = expected_items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * false_positive_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as u32;
+
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    pub fn insert(&mut self, password: &str) {
+        let positions: Vec<u64> = self.positions(password).collect();
+        for pos in positions {
+            self.bits[(pos / 64) as usize] |= 1 << (pos % 64);
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        Ok(bincode::serialize_into(BufWriter::new(File::create(path)?), self)?)
+    }
+}
+
+impl BloomFilter {
+    // Double hashing on the SHA-1 digest gives the positions of the password in the filter
+    fn positions(&self, password: &str) -> impl Iterator<Item = u64> + '_ {
+        let digest = Sha1::digest(password.as_bytes());
+        let h1 = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    /**
+    Parameter: password - password to look up
+    Return: Bool - True if the password is probably in the list, false if it surely isn't
+     **/
+    pub fn contains(&self, password: &str) -> bool {
+        self.positions(password)
+            .all(|pos| self.bits[(pos / 64) as usize] & (1 << (pos % 64)) != 0)
+    }
+
+    /// The size of the filter is checked, so that a damaged file is refused here and not on each lookup
+    pub fn load(path: &str) -> Result<BloomFilter, Box<dyn Error>> {
+        let filter: BloomFilter = bincode::deserialize_from(BufReader::new(File::open(path)?))?;
+        let too_short = (filter.bits.len() as u64) < filter.num_bits.div_ceil(64);
+        if filter.num_bits == 0 || filter.num_hashes == 0 || too_short {
+            return Err("Invalid breached passwords filter".into());
+        }
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // File of the temporary directory, unique per test
+    fn temp_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!("lab3_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn inserted_passwords_are_found_after_a_round_trip() {
+        let mut filter = BloomFilter::new(100, 0.001);
+        for password in ["123456", "password", "qwerty"] {
+            filter.insert(password);
+        }
+        let path = temp_path("round_trip");
+        filter.save(&path).unwrap();
+        let loaded = BloomFilter::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for password in ["123456", "password", "qwerty"] {
+            assert!(loaded.contains(password));
+        }
+        assert!(!loaded.contains("correct horse battery staple"));
+    }
+
+    #[test]
+    fn damaged_filters_are_refused() {
+        let damaged = [
+            BloomFilter { bits: vec![0; 2], num_bits: 0, num_hashes: 3 },
+            BloomFilter { bits: vec![0; 1], num_bits: 128, num_hashes: 3 },
+            BloomFilter { bits: vec![0; 2], num_bits: 128, num_hashes: 0 },
+        ];
+        for (i, filter) in damaged.iter().enumerate() {
+            let path = temp_path(&format!("damaged_{}", i));
+            filter.save(&path).unwrap();
+            let loaded = BloomFilter::load(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(loaded.is_err());
+        }
+    }
+}
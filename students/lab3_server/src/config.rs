@@ -0,0 +1,169 @@
+/// This file is used to load the server configuration
+///
+/// The configuration is read once from `config/server.ron`, missing fields use the defaults
+use crate::user::ProfileField;
+use lazy_static::lazy_static;
+use log::warn;
+use phonenumber::country;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const CONFIG_PATH: &str = "config/server.ron";
+
+lazy_static! {
+    pub static ref CONFIG: Config = Config::load(CONFIG_PATH);
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub argon2: Argon2Params,
+    /// Key file containing the versioned peppers, no pepper is used if absent
+    pub pepper_file: Option<String>,
+    /// Bloom filter of breached passwords built by `build_breach_filter`, no check if absent
+    pub breached_passwords_filter: Option<String>,
+    pub password_policy: PasswordPolicy,
+    /// Region of the phone numbers entered without country code
+    pub default_phone_region: country::Id,
+    /// Usernames that can't be given to new accounts, lookalikes included
+    pub reserved_usernames: Vec<String>,
+    /// Profile fields that users can change themselves, HR can change every field
+    pub self_service_fields: Vec<ProfileField>,
+    /// Registrations that a client address can submit, whether they succeed or not
+    pub registration_limit: RateLimit,
+    /// Hours during which an invitation code can be used
+    pub invite_validity_hours: i64,
+    pub session: SessionPolicy,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            argon2: Argon2Params::default(),
+            pepper_file: None,
+            breached_passwords_filter: None,
+            password_policy: PasswordPolicy::default(),
+            default_phone_region: country::Id::CH,
+            reserved_usernames: ["admin", "administrator", "root", "system", "anonymous", "support", "hr"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            self_service_fields: vec![
+                ProfileField::PhoneNumber,
+                ProfileField::FullName,
+                ProfileField::Email,
+                ProfileField::Office,
+            ],
+            registration_limit: RateLimit::default(),
+            invite_validity_hours: 72,
+            session: SessionPolicy::default(),
+        }
+    }
+}
+
+/// Cost parameters used for the Argon2id password hashes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct Argon2Params {
+    /// Memory cost in KiB
+    pub mem_cost: u32,
+    /// Number of passes
+    pub time_cost: u32,
+    /// Number of lanes
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Argon2Params {
+            mem_cost: 19 * 1024,
+            time_cost: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Rules that new passwords must follow
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Minimum zxcvbn score, from 0 to 4
+    pub min_score: u8,
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Words that can't appear in a password, the username is always banned
+    pub banned_words: Vec<String>,
+    /// Number of previous passwords that can't be reused
+    pub history_size: usize,
+    /// Days after which a password must be changed, never expires if absent
+    pub max_age_days: Option<i64>,
+    /// Days before the expiry from which the user is warned
+    pub expiry_warning_days: i64,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_score: 3,
+            min_length: 8,
+            max_length: 64,
+            banned_words: Vec::new(),
+            history_size: 5,
+            max_age_days: Some(90),
+            expiry_warning_days: 14,
+        }
+    }
+}
+
+/// Limits after which a logged in user goes back to anonymous and must log in again
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SessionPolicy {
+    /// Minutes without any action, no limit if absent
+    pub idle_timeout_minutes: Option<i64>,
+    /// Hours since the login, no limit if absent
+    pub max_lifetime_hours: Option<i64>,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        SessionPolicy {
+            idle_timeout_minutes: Some(15),
+            max_lifetime_hours: Some(8),
+        }
+    }
+}
+
+/// Maximum number of attempts in a sliding window
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RateLimit {
+    pub max_attempts: usize,
+    pub window_secs: u64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            max_attempts: 3,
+            window_secs: 3600,
+        }
+    }
+}
+
+impl Config {
+    /**
+    Parameter: path - path of the RON configuration file
+    Return: Config - Loaded configuration, or the default one if the file doesn't exist
+     **/
+    fn load(path: &str) -> Config {
+        if !Path::new(path).exists() {
+            warn!("No configuration found at {}, using default values", path);
+            return Config::default();
+        }
+
+        let content = fs::read_to_string(path).expect("Cannot read configuration file");
+        ron::from_str(&content).expect("Invalid configuration file")
+    }
+}
@@ -2,15 +2,27 @@ use native_tls::TlsStream;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::error::Error;
-use std::net::TcpStream;
+use std::net::{IpAddr, TcpStream};
 
 pub struct Connection {
     stream: TlsStream<TcpStream>,
+    peer_ip: Option<IpAddr>,
 }
 
 impl Connection {
     pub fn new(stream: TlsStream<TcpStream>) -> Connection {
-        Connection { stream }
+        let peer_ip = stream.get_ref().peer_addr().ok().map(|addr| addr.ip());
+        Connection { stream, peer_ip }
+    }
+
+    /// Second handle on the socket, used to close the connection from another thread
+    pub fn stream_handle(&self) -> Option<TcpStream> {
+        self.stream.get_ref().try_clone().ok()
+    }
+
+    /// Address of the client, None if it couldn't be read
+    pub fn peer_ip(&self) -> Option<IpAddr> {
+        self.peer_ip
     }
 
     pub fn send<T>(&mut self, o: &T) -> Result<(), Box<dyn Error>>
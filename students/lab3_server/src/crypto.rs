@@ -1,5 +1,135 @@
-use argon2::{self};
+use crate::config::{Argon2Params, CONFIG};
+use argon2::{self, ThreadMode, Variant, Version};
+use lazy_static::lazy_static;
+use crate::validate_inputs::INVITE_CODE_LENGTH;
 use rand::{thread_rng, Rng};
+use sha1::{Digest, Sha1};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+lazy_static! {
+    static ref PEPPERS: Option<Peppers> = CONFIG.pepper_file.as_deref().map(Peppers::load);
+    /// Hash verified when the username doesn't exist, so that the login takes the same time
+    static ref DUMMY_HASH: (String, Option<u32>) =
+        generate_hash("dummy password", &generate_salt()).expect("Cannot generate dummy hash");
+}
+
+/// Errors returned by the hashing functions
+#[derive(Debug)]
+pub enum CryptoError {
+    /// Argon2 failed, e.g. because a stored hash is malformed
+    Argon2(argon2::Error),
+    /// The hash was made with a pepper version that isn't in the key file anymore
+    UnknownPepper(u32),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::Argon2(e) => write!(f, "Argon2 error: {}", e),
+            CryptoError::UnknownPepper(v) => write!(f, "Pepper version {} is not available", v),
+        }
+    }
+}
+
+impl Error for CryptoError {}
+
+impl From<argon2::Error> for CryptoError {
+    fn from(e: argon2::Error) -> Self {
+        CryptoError::Argon2(e)
+    }
+}
+
+/// Content of the pepper key file, the secrets are hex encoded
+#[derive(Deserialize)]
+struct PepperFile {
+    current: u32,
+    versions: HashMap<u32, String>,
+}
+
+/// Versioned secrets given to Argon2, old versions are kept to verify existing hashes
+struct Peppers {
+    current: u32,
+    versions: HashMap<u32, Vec<u8>>,
+}
+
+impl Peppers {
+    /// The key file is a deployment secret, never in the repository, the server doesn't start without it
+    fn load(path: &str) -> Peppers {
+        let content = fs::read_to_string(path).unwrap_or_else(|e| {
+            panic!(
+                "Cannot read pepper file {}: {}. Create it from keys/pepper.example.ron with a random secret, \
+                 e.g. from `openssl rand -hex 32`",
+                path, e
+            )
+        });
+        let file: PepperFile = ron::from_str(&content).expect("Invalid pepper file");
+
+        let versions: HashMap<u32, Vec<u8>> = file
+            .versions
+            .iter()
+            .map(|(version, hex)| (*version, decode_hex(hex).expect("Invalid pepper encoding")))
+            .collect();
+        assert!(
+            versions.contains_key(&file.current),
+            "Current pepper version is missing from the pepper file"
+        );
+
+        Peppers {
+            current: file.current,
+            versions,
+        }
+    }
+}
+
+/**
+Parameter: None
+Return: None - Loads the pepper file and the dummy hash now so that errors are detected at startup
+ **/
+pub fn init() {
+    lazy_static::initialize(&PEPPERS);
+    lazy_static::initialize(&DUMMY_HASH);
+}
+
+/**
+Parameter: hex - hex encoded string
+Return: Option<Vec<u8>> - Decoded bytes, None if the string isn't valid hex
+ **/
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/**
+Parameter: None
+Return: Option<u32> - Version of the pepper used for new hashes, None if peppering is disabled
+ **/
+fn current_pepper_version() -> Option<u32> {
+    PEPPERS.as_ref().map(|p| p.current)
+}
+
+/**
+Parameter: version - pepper version to look up
+Return: Result<&[u8], CryptoError> - Secret of this version, empty if no pepper is used
+ **/
+fn pepper(version: Option<u32>) -> Result<&'static [u8], CryptoError> {
+    match version {
+        None => Ok(&[]),
+        Some(v) => PEPPERS
+            .as_ref()
+            .and_then(|p| p.versions.get(&v))
+            .map(|p| p.as_slice())
+            .ok_or(CryptoError::UnknownPepper(v)),
+    }
+}
 
 /**
 Parameter: None
@@ -10,20 +140,157 @@ pub fn generate_salt() -> Vec<u8> {
     salt.to_vec()
 }
 
+/**
+Parameter: None
+Return: String - Random invitation code of 128 bits, in hexadecimal
+ **/
+pub fn generate_invite_code() -> String {
+    let bytes: [u8; INVITE_CODE_LENGTH / 2] = thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/**
+Parameter: code - invitation code, as generated or once validated
+Return: String - Digest stored instead of the code, a random code of 128 bits doesn't need a slow hash
+ **/
+pub fn invite_code_digest(code: &str) -> String {
+    Sha1::digest(code.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/**
+Parameters: params - cost parameters to use
+            secret - pepper given to Argon2
+Return: Config - Argon2id configuration
+ **/
+fn argon2_config<'a>(params: &Argon2Params, secret: &'a [u8]) -> argon2::Config<'a> {
+    argon2::Config {
+        secret,
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: params.mem_cost,
+        time_cost: params.time_cost,
+        lanes: params.parallelism,
+        thread_mode: ThreadMode::from_threads(params.parallelism),
+        ..Default::default()
+    }
+}
+
 /**
 Parameters: password - password to hash
             salt     - salt used to hash the password
-Return: String - Hashed password
+Return: Result<(String, Option<u32>), CryptoError> - Hashed password and the pepper version used
+ **/
+pub fn generate_hash(password: &str, salt: &[u8]) -> Result<(String, Option<u32>), CryptoError> {
+    let version = current_pepper_version();
+    let secret = pepper(version)?;
+    let hash = argon2::hash_encoded(password.as_bytes(), salt, &argon2_config(&CONFIG.argon2, secret))?;
+    Ok((hash, version))
+}
+
+/**
+Parameters: hash           - hash of the password to verify
+            pepper_version - pepper version used to create the hash
+            password       - plain password to verify
+Return: Result<bool, CryptoError> - Result of the verification, an error if the hash can't be used
+ **/
+pub fn verify_hash(hash: &str, pepper_version: Option<u32>, password: &str) -> Result<bool, CryptoError> {
+    let secret = pepper(pepper_version)?;
+    Ok(argon2::verify_encoded_ext(hash, password.as_bytes(), secret, &[])?)
+}
+
+/**
+Parameter: password - plain password received for an unknown username
+Return: None - Verifies the password against a fixed hash to spend the same time as a real login
  **/
-pub fn generate_hash(password: &String, salt: &Vec<u8>) -> String {
-    argon2::hash_encoded(&password.as_bytes(), &salt.to_vec(), &Default::default()).unwrap()
+pub fn dummy_verify(password: &str) {
+    let (hash, pepper_version) = &*DUMMY_HASH;
+    let _ = verify_hash(hash, *pepper_version, password);
 }
 
 /**
-Parameters: hash     - hash of the password to verify
-            password - plain password to verify
-Return: Bool - Result of the verification
+Parameters: hash           - encoded hash stored in the database
+            pepper_version - pepper version used to create the hash
+Return: Bool - True if the hash wasn't made with Argon2id, the current cost parameters and the current pepper
  **/
-pub fn verify_hash(hash: &String, password: &String) -> bool {
-    argon2::verify_encoded(&hash, password.as_ref()).unwrap()
-}
\ No newline at end of file
+pub fn needs_rehash(hash: &str, pepper_version: Option<u32>) -> bool {
+    is_outdated(hash, pepper_version, current_pepper_version(), &CONFIG.argon2)
+}
+
+/**
+Parameters: hash           - encoded hash stored in the database
+            pepper_version - pepper version used to create the hash
+            current_pepper - pepper version used for new hashes
+            params         - cost parameters used for new hashes
+Return: Bool - True if the hash must be replaced by one made with the current settings
+ **/
+fn is_outdated(hash: &str, pepper_version: Option<u32>, current_pepper: Option<u32>, params: &Argon2Params) -> bool {
+    if pepper_version != current_pepper {
+        return true;
+    }
+
+    // Encoded format: $argon2id$v=19$m=<mem>,t=<time>,p=<lanes>$<salt>$<hash>
+    let fields: Vec<&str> = hash.split('$').collect();
+    if fields.len() != 6 || fields[1] != Variant::Argon2id.as_lowercase_str() || fields[2] != "v=19" {
+        return true;
+    }
+
+    let expected = format!(
+        "m={},t={},p={}",
+        params.mem_cost, params.time_cost, params.parallelism
+    );
+    fields[3] != expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small costs, the tests only check the encoded parameters
+    fn params(mem_cost: u32, time_cost: u32) -> Argon2Params {
+        Argon2Params {
+            mem_cost,
+            time_cost,
+            parallelism: 1,
+        }
+    }
+
+    fn hash_with(params: &Argon2Params, variant: Variant) -> String {
+        let config = argon2::Config {
+            variant,
+            ..argon2_config(params, &[])
+        };
+        argon2::hash_encoded(b"password", b"saltsaltsaltsalt", &config).unwrap()
+    }
+
+    #[test]
+    fn current_hash_is_kept() {
+        let current = params(64, 1);
+        assert!(!is_outdated(&hash_with(&current, Variant::Argon2id), Some(2), Some(2), &current));
+    }
+
+    #[test]
+    fn old_parameters_are_rehashed() {
+        let old = hash_with(&params(64, 1), Variant::Argon2id);
+        assert!(is_outdated(&old, None, None, &params(128, 1)));
+        assert!(is_outdated(&old, None, None, &params(64, 2)));
+    }
+
+    #[test]
+    fn other_variant_is_rehashed() {
+        let current = params(64, 1);
+        assert!(is_outdated(&hash_with(&current, Variant::Argon2i), None, None, &current));
+    }
+
+    #[test]
+    fn pepper_change_is_rehashed() {
+        let current = params(64, 1);
+        let hash = hash_with(&current, Variant::Argon2id);
+        assert!(is_outdated(&hash, Some(1), Some(2), &current));
+        assert!(is_outdated(&hash, None, Some(1), &current));
+    }
+
+    #[test]
+    fn malformed_hash_is_rehashed() {
+        assert!(is_outdated("not a hash", None, None, &params(64, 1)));
+    }
+}
@@ -1,10 +1,14 @@
 use crate::crypto::{generate_hash, generate_salt};
+use crate::validate_inputs::{username_key, PhoneNumber, Username};
 /// This file is used to store and retrieve user accounts from the database
 ///
 /// Tasks todo: - Log stuff whenever required
 ///             - Potential improvements
-use crate::user::{UserAccount, UserRole};
+use crate::approval::{Operation, PendingRequest};
+use crate::user::{FieldChange, Invitation, UserAccount, UserRole};
+use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
+use log::{error, info, warn};
 use rustbreak::{deser::Ron, FileDatabase};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -18,55 +22,302 @@ lazy_static! {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Database {
     data: HashMap<String, UserAccount>,
+    /// Operations waiting for the approval of a second HR user
+    #[serde(default)]
+    pending: Vec<PendingRequest>,
+    #[serde(default)]
+    last_pending_id: u32,
+    /// Invitations not used yet, the expired ones are removed when a new one is added
+    #[serde(default)]
+    invitations: Vec<Invitation>,
+    /// History of the deleted accounts, ending with their deletion
+    #[serde(default)]
+    deleted_histories: HashMap<String, Vec<FieldChange>>,
 }
 
 impl Database {
     pub fn insert(user: &UserAccount) -> Result<(), Box<dyn Error>> {
-        DB.write(|db| db.data.insert(user.username().to_string(), user.clone()))?;
+        DB.write(|db| db.data.insert(username_key(user.username()), user.clone()))?;
         Ok(DB.save()?)
     }
 
-    pub fn get(username: &str) -> Result<Option<UserAccount>, Box<dyn Error>> {
-        Ok(match DB.borrow_data()?.data.get(username) {
-            Some(user) => Some(user.clone()),
-            None => None,
-        })
+    pub fn get(username: &Username) -> Result<Option<UserAccount>, Box<dyn Error>> {
+        Ok(DB.borrow_data()?.data.get(&username_key(username)).cloned())
     }
 
     pub fn values() -> Result<Vec<UserAccount>, Box<dyn Error>> {
         Ok(DB.borrow_data()?.data.values().cloned().collect())
     }
+
+    /// Removes an account, its history is kept and replaces the one of a previous account with the same username
+    pub fn remove(user: &UserAccount) -> Result<Option<UserAccount>, Box<dyn Error>> {
+        let key = username_key(user.username());
+        let removed = DB.write(|db| {
+            let removed = db.data.remove(&key)?;
+            db.deleted_histories.insert(key, user.history().to_vec());
+            Some(removed)
+        })?;
+        DB.save()?;
+        Ok(removed)
+    }
+
+    pub fn deleted_history(username: &Username) -> Result<Option<Vec<FieldChange>>, Box<dyn Error>> {
+        Ok(DB.borrow_data()?.deleted_histories.get(&username_key(username)).cloned())
+    }
+
+    /**
+    Parameters: operation    - operation to approve
+                requested_by - HR user asking for the operation, None for a self-registration
+    Return: Result<u32, Box<dyn Error>> - Number of the pending request
+     **/
+    pub fn add_pending(operation: Operation, requested_by: Option<&Username>) -> Result<u32, Box<dyn Error>> {
+        let id = DB.write(|db| {
+            db.last_pending_id += 1;
+            db.pending.push(PendingRequest {
+                id: db.last_pending_id,
+                operation,
+                requested_by: requested_by.cloned(),
+                requested_at: Utc::now(),
+            });
+            db.last_pending_id
+        })?;
+        DB.save()?;
+        Ok(id)
+    }
+
+    pub fn pending() -> Result<Vec<PendingRequest>, Box<dyn Error>> {
+        Ok(DB.borrow_data()?.pending.clone())
+    }
+
+    pub fn get_pending(id: u32) -> Result<Option<PendingRequest>, Box<dyn Error>> {
+        Ok(DB.borrow_data()?.pending.iter().find(|request| request.id == id).cloned())
+    }
+
+    /// Removes a pending request, only one of two concurrent approvals gets it
+    pub fn take_pending(id: u32) -> Result<Option<PendingRequest>, Box<dyn Error>> {
+        let request = DB.write(|db| {
+            let index = db.pending.iter().position(|request| request.id == id)?;
+            Some(db.pending.remove(index))
+        })?;
+        DB.save()?;
+        Ok(request)
+    }
+
+    /// Puts back a request taken by an approval that failed
+    pub fn restore_pending(request: PendingRequest) -> Result<(), Box<dyn Error>> {
+        DB.write(|db| {
+            let index = db.pending.partition_point(|pending| pending.id < request.id);
+            db.pending.insert(index, request);
+        })?;
+        Ok(DB.save()?)
+    }
+
+    pub fn add_invitation(invitation: Invitation) -> Result<(), Box<dyn Error>> {
+        DB.write(|db| {
+            let now = Utc::now();
+            db.invitations.retain(|invitation| invitation.expires_at > now);
+            db.invitations.push(invitation);
+        })?;
+        Ok(DB.save()?)
+    }
+
+    /// Invitations that haven't expired
+    pub fn invitations() -> Result<Vec<Invitation>, Box<dyn Error>> {
+        let now = Utc::now();
+        let db = DB.borrow_data()?;
+        Ok(db.invitations.iter().filter(|invitation| invitation.expires_at > now).cloned().collect())
+    }
+
+    pub fn get_invitation(code_digest: &str) -> Result<Option<Invitation>, Box<dyn Error>> {
+        Ok(Database::invitations()?.into_iter().find(|invitation| invitation.code_digest == code_digest))
+    }
+
+    /// Removes an invitation, only one of two concurrent uses of a code gets it
+    pub fn take_invitation(code_digest: &str) -> Result<Option<Invitation>, Box<dyn Error>> {
+        let now = Utc::now();
+        let invitation = DB.write(|db| db.remove_invitation(code_digest, now))?;
+        DB.save()?;
+        Ok(invitation)
+    }
+
+    /// Number of HR accounts, the four-eyes requests need at least two of them
+    pub fn hr_count() -> Result<usize, Box<dyn Error>> {
+        Ok(DB.borrow_data()?.data.values().filter(|user| *user.role() == UserRole::HR).count())
+    }
+
+    /**
+    Parameter: username - standard user to promote
+    Return: Result<(), Box<dyn Error>> - Makes the user HR without an approval, only allowed while there
+            are fewer than two HR accounts, e.g. for a database created with a single one
+     **/
+    pub fn promote_first_hr(username: &Username) -> Result<(), Box<dyn Error>> {
+        DB.write(|db| -> Result<(), Box<dyn Error>> {
+            let hr_count = db.data.values().filter(|user| *user.role() == UserRole::HR).count();
+            if hr_count >= 2 {
+                let message = format!("There are already {} HR accounts, the role must be changed with an approval", hr_count);
+                return Err(message.into());
+            }
+            let user = db.data.get_mut(&username_key(username)).ok_or("Unknown account")?;
+            if *user.role() == UserRole::HR {
+                return Err("The account is already HR".into());
+            }
+            user.change_role(UserRole::HR, &Username::parse("system").unwrap());
+            Ok(())
+        })??;
+        Ok(DB.save()?)
+    }
+
+    /// Converts the data stored by previous versions of the server
+    pub fn migrate() -> Result<(), Box<dyn Error>> {
+        DB.borrow_data()?.report_invalid_usernames();
+        let migrated = DB.write(|db| db.migrate_phone_numbers() + db.migrate_username_keys())?;
+
+        if migrated > 0 {
+            DB.save()?;
+        }
+        Ok(())
+    }
+
+    // Accounts created before the username policy can't log in anymore, they aren't renamed automatically
+    // Removes the invitation of a code, even an expired one, which is then not returned
+    fn remove_invitation(&mut self, code_digest: &str, now: DateTime<Utc>) -> Option<Invitation> {
+        let index = self.invitations.iter().position(|invitation| invitation.code_digest == code_digest)?;
+        Some(self.invitations.remove(index)).filter(|invitation| invitation.expires_at > now)
+    }
+
+    fn report_invalid_usernames(&self) {
+        for user in self.data.values() {
+            if Username::parse(user.username()).is_err() {
+                error!("Account {} doesn't follow the username policy and must be renamed manually", user.username());
+            }
+        }
+    }
+
+    // Phone numbers stored before the E.164 normalisation
+    fn migrate_phone_numbers(&mut self) -> usize {
+        let mut migrated = 0;
+        for user in self.data.values_mut() {
+            if user.phone_number().starts_with('+') {
+                continue;
+            }
+            match PhoneNumber::parse(user.phone_number()) {
+                Ok(phone) => {
+                    info!("Phone number of {} migrated to {}", user.username(), &*phone);
+                    user.set_phone_number(phone);
+                    migrated += 1;
+                }
+                Err(_) => warn!("Cannot migrate the phone number of {}", user.username()),
+            }
+        }
+        migrated
+    }
+
+    // Accounts stored under their exact username before the case-insensitive keys
+    fn migrate_username_keys(&mut self) -> usize {
+        let mut migrated = 0;
+        let outdated: Vec<String> = self
+            .data
+            .iter()
+            .filter(|(key, user)| **key != username_key(user.username()))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for old_key in outdated {
+            let key = username_key(self.data[&old_key].username());
+            if self.data.contains_key(&key) {
+                error!("Account {} conflicts with another username and must be renamed manually", old_key);
+                continue;
+            }
+            let user = self.data.remove(&old_key).unwrap();
+            self.data.insert(key, user);
+            migrated += 1;
+        }
+        migrated
+    }
 }
 
 impl Default for Database {
+    /// Two HR accounts are created, so that the requests of one can be approved by the other. Their
+    /// password is public, it must be changed at the first login
     fn default() -> Self {
         let mut db = Database {
             data: HashMap::new(),
+            pending: Vec::new(),
+            last_pending_id: 0,
+            invitations: Vec::new(),
+            deleted_histories: HashMap::new(),
         };
 
-        let password = "default_pass".to_string();
-        let salt_1 = generate_salt();
-        let salt_2 = generate_salt();
-        let hash_password_1 = generate_hash(&password, &salt_1);
-        let hash_password_2 = generate_hash(&password, &salt_2);
-
-        let u1 = UserAccount::new(
-            "default_user".to_string(),
-            hash_password_1,
-            "0784539872".to_string(),
-            UserRole::StandardUser,
-        );
-
-        let u2 = UserAccount::new(
-            "default_hr".to_string(),
-            hash_password_2,
-            "0793175289".to_string(),
-            UserRole::HR,
-        );
-
-        db.data.insert(u1.username().to_string(), u1);
-        db.data.insert(u2.username().to_string(), u2);
+        let accounts = [
+            ("default_user", "+41784539872", UserRole::StandardUser),
+            ("default_hr", "+41793175289", UserRole::HR),
+            ("default_hr2", "+41793175290", UserRole::HR),
+        ];
+        for (username, phone, role) in accounts {
+            let (hash_password, pepper_version) =
+                generate_hash("default_pass", &generate_salt()).expect("Cannot hash default password");
+            let mut user = UserAccount::new(
+                Username::parse(username).unwrap(),
+                hash_password,
+                pepper_version,
+                PhoneNumber::parse(phone).unwrap(),
+                role,
+            );
+            user.expire_password();
+            db.data.insert(username_key(user.username()), user);
+        }
 
         db
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn invitation(code_digest: &str, expires_at: DateTime<Utc>) -> Invitation {
+        Invitation {
+            code_digest: code_digest.to_string(),
+            username: Username::parse("alice").unwrap(),
+            role: UserRole::StandardUser,
+            created_by: Username::parse("bob").unwrap(),
+            expires_at,
+        }
+    }
+
+    fn database(invitations: Vec<Invitation>) -> Database {
+        Database {
+            data: HashMap::new(),
+            pending: Vec::new(),
+            last_pending_id: 0,
+            invitations,
+            deleted_histories: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn invitation_can_only_be_taken_once() {
+        let now = Utc::now();
+        let mut db = database(vec![invitation("digest", now + Duration::hours(1))]);
+        assert!(db.remove_invitation("digest", now).is_some());
+        assert!(db.remove_invitation("digest", now).is_none());
+    }
+
+    #[test]
+    fn expired_invitation_is_refused() {
+        let now = Utc::now();
+        let mut db = database(vec![invitation("digest", now)]);
+        assert!(db.remove_invitation("digest", now).is_none());
+        // It is removed anyway
+        assert!(db.invitations.is_empty());
+    }
+
+    #[test]
+    fn unknown_code_is_refused() {
+        let now = Utc::now();
+        let mut db = database(vec![invitation("digest", now + Duration::hours(1))]);
+        assert!(db.remove_invitation("other", now).is_none());
+        assert_eq!(db.invitations.len(), 1);
+    }
+}
@@ -0,0 +1,1084 @@
+/// This file is used to implement the actions, each one as an `ActionHandler` run by `dispatch`
+use crate::action::{
+    password_expiry, ActionError, Field, ActionHandler, AddUserRequest, ChainOfCommandRequest,
+    ChangePasswordRequest, ChangeRoleRequest, ConnectedUser, CreateInviteRequest, DeleteUserRequest, DirectReportsRequest,
+    LoginRequest, MatchMode, PendingDecisionRequest, RedeemInviteRequest, RegisterRequest, Response, RevertFieldRequest, SearchUsersRequest,
+    SortField, TerminateSessionRequest, UpdateOwnProfileRequest, UpdateProfileRequest, ViewHistoryRequest, ViewProfileRequest,
+};
+use crate::approval::{Operation, PendingView};
+use crate::config::CONFIG;
+use crate::crypto::{
+    dummy_verify, generate_hash, generate_invite_code, generate_salt, invite_code_digest, needs_rehash, verify_hash,
+};
+use crate::database::Database;
+use crate::hierarchy::Hierarchy;
+use crate::rate_limit::REGISTRATIONS;
+use crate::session;
+use crate::user::{Invitation, ProfileField, ProfileUpdate, ProfileView, UserAccount, UserRole, UserView, Viewer};
+use crate::validate_inputs::{
+    are_confusable, is_reserved_username, InviteCode, parse_page_size, parse_profile_update, username_key, Password, PasswordRejection, PhoneNumber,
+    SearchQuery, Username, ValidationError,
+};
+use chrono::{Duration, Utc};
+use log::{error, info, warn};
+
+pub struct ShowUsers;
+pub struct UpdateOwnProfile;
+pub struct UpdateProfile;
+pub struct AddUser;
+pub struct ChangeRole;
+pub struct DeleteUser;
+pub struct ListPending;
+pub struct Approve;
+pub struct Reject;
+pub struct Register;
+pub struct CreateInvite;
+pub struct RedeemInvite;
+pub struct ListMySessions;
+pub struct ListSessions;
+pub struct TerminateSession;
+pub struct Login;
+pub struct Logout;
+pub struct ChangePassword;
+pub struct SearchUsers;
+pub struct ViewProfile;
+pub struct ChainOfCommand;
+pub struct DirectReports;
+pub struct OrgChart;
+pub struct ViewHistory;
+pub struct RevertField;
+
+impl ActionHandler for ShowUsers {
+    const OBJECT: &'static str = "show_users";
+    type Request = ();
+    type Input = ();
+    type Output = Vec<UserView>;
+
+    fn validate(_request: (), _u: &ConnectedUser) -> Result<(), ActionError> {
+        Ok(())
+    }
+
+    fn execute(_input: (), u: &mut ConnectedUser) -> Result<Vec<UserView>, ActionError> {
+        let viewer = u.viewer()?;
+        Ok(Database::values()?.iter().map(|user| UserView::new(user, viewer)).collect())
+    }
+}
+
+impl ActionHandler for UpdateOwnProfile {
+    const OBJECT: &'static str = "update_own_profile";
+    type Request = UpdateOwnProfileRequest;
+    type Input = Vec<ProfileUpdate>;
+    type Output = ();
+
+    fn validate(request: UpdateOwnProfileRequest, _u: &ConnectedUser) -> Result<Vec<ProfileUpdate>, ActionError> {
+        validate_changes(request.changes, |field| CONFIG.self_service_fields.contains(&field))
+    }
+
+    fn execute(updates: Vec<ProfileUpdate>, u: &mut ConnectedUser) -> Result<(), ActionError> {
+        let mut user = u.user_account()?;
+        let changed_by = user.username().clone();
+        apply_changes(&mut user, updates, &changed_by)
+    }
+}
+
+impl ActionHandler for UpdateProfile {
+    const OBJECT: &'static str = "update_profile";
+    type Request = UpdateProfileRequest;
+    type Input = (Username, Vec<ProfileUpdate>);
+    type Output = ();
+
+    fn validate(request: UpdateProfileRequest, _u: &ConnectedUser) -> Result<Self::Input, ActionError> {
+        let username = Username::parse(&request.username).field("username")?;
+        Ok((username, validate_changes(request.changes, |_| true)?))
+    }
+
+    fn execute((username, updates): Self::Input, u: &mut ConnectedUser) -> Result<(), ActionError> {
+        let changed_by = u.account_name().cloned().ok_or(ActionError::Unauthorized)?;
+        let mut target_user = Database::get(&username)?.ok_or(ActionError::NotFound)?;
+        apply_changes(&mut target_user, updates, &changed_by)?;
+        info!("Profile of user {} changed from {}", target_user.username(), u.display_name());
+        Ok(())
+    }
+}
+
+impl ActionHandler for ViewProfile {
+    const OBJECT: &'static str = "view_profile";
+    type Request = ViewProfileRequest;
+    type Input = Option<Username>;
+    type Output = Response;
+
+    fn validate(request: ViewProfileRequest, _u: &ConnectedUser) -> Result<Option<Username>, ActionError> {
+        request.username.map(|username| Username::parse(&username)).transpose().field("username")
+    }
+
+    fn execute(username: Option<Username>, u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let viewer = u.viewer()?;
+        let user = target_account(username, u)?;
+        Ok(Response::Profile(ProfileView::new(&user, viewer)))
+    }
+}
+
+impl ActionHandler for ChainOfCommand {
+    const OBJECT: &'static str = "chain_of_command";
+    type Request = ChainOfCommandRequest;
+    type Input = Option<Username>;
+    type Output = Vec<UserView>;
+
+    fn validate(request: ChainOfCommandRequest, _u: &ConnectedUser) -> Result<Option<Username>, ActionError> {
+        request.username.map(|username| Username::parse(&username)).transpose().field("username")
+    }
+
+    fn execute(username: Option<Username>, u: &mut ConnectedUser) -> Result<Vec<UserView>, ActionError> {
+        let viewer = u.viewer()?;
+        let user = target_account(username, u)?;
+        let hierarchy = Hierarchy::load()?;
+        Ok(hierarchy.chain_of_command(&user).into_iter().map(|manager| UserView::new(manager, viewer)).collect())
+    }
+}
+
+impl ActionHandler for DirectReports {
+    const OBJECT: &'static str = "direct_reports";
+    type Request = DirectReportsRequest;
+    type Input = Option<Username>;
+    type Output = Vec<UserView>;
+
+    fn validate(request: DirectReportsRequest, _u: &ConnectedUser) -> Result<Option<Username>, ActionError> {
+        request.username.map(|username| Username::parse(&username)).transpose().field("username")
+    }
+
+    fn execute(username: Option<Username>, u: &mut ConnectedUser) -> Result<Vec<UserView>, ActionError> {
+        let viewer = u.viewer()?;
+        let user = target_account(username, u)?;
+        let hierarchy = Hierarchy::load()?;
+        Ok(hierarchy.direct_reports(&user).into_iter().map(|report| UserView::new(report, viewer)).collect())
+    }
+}
+
+impl ActionHandler for OrgChart {
+    const OBJECT: &'static str = "org_chart";
+    type Request = ();
+    type Input = ();
+    type Output = Response;
+
+    fn validate(_request: (), _u: &ConnectedUser) -> Result<(), ActionError> {
+        Ok(())
+    }
+
+    fn execute(_input: (), _u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        Ok(Response::OrgChart(Hierarchy::load()?.to_dot()))
+    }
+}
+
+impl ActionHandler for ViewHistory {
+    const OBJECT: &'static str = "view_history";
+    type Request = ViewHistoryRequest;
+    type Input = Username;
+    type Output = Response;
+
+    fn validate(request: ViewHistoryRequest, _u: &ConnectedUser) -> Result<Username, ActionError> {
+        Username::parse(&request.username).field("username")
+    }
+
+    fn execute(username: Username, _u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let history = match Database::get(&username)? {
+            Some(user) => user.history().to_vec(),
+            None => Database::deleted_history(&username)?.ok_or(ActionError::NotFound)?,
+        };
+        Ok(Response::History(history))
+    }
+}
+
+impl ActionHandler for RevertField {
+    const OBJECT: &'static str = "revert_field";
+    type Request = RevertFieldRequest;
+    type Input = (Username, u32);
+    type Output = ();
+
+    fn validate(request: RevertFieldRequest, _u: &ConnectedUser) -> Result<(Username, u32), ActionError> {
+        Ok((Username::parse(&request.username).field("username")?, request.version))
+    }
+
+    fn execute((username, version): (Username, u32), u: &mut ConnectedUser) -> Result<(), ActionError> {
+        let changed_by = u.account_name().cloned().ok_or(ActionError::Unauthorized)?;
+        let mut target_user = Database::get(&username)?.ok_or(ActionError::NotFound)?;
+        let change = target_user
+            .history()
+            .iter()
+            .find(|change| change.version == version)
+            .cloned()
+            .ok_or(ActionError::NotFound)?;
+
+        // The old value is validated again, the rules may have changed since it was stored
+        let old_value = change.old_value.unwrap_or_default();
+        let update = parse_profile_update(change.field, &old_value).field(change.field.name())?;
+        apply_changes(&mut target_user, vec![update], &changed_by)?;
+        info!(
+            "Change {} of the {} of user {} reverted from {}",
+            version,
+            change.field.name(),
+            target_user.username(),
+            u.display_name()
+        );
+        Ok(())
+    }
+}
+
+/**
+Parameters: username - account named in the request, None for the connected user
+            u        - connected user performing the action
+Return: Result<UserAccount, ActionError> - Account targeted by the action
+ **/
+fn target_account(username: Option<Username>, u: &mut ConnectedUser) -> Result<UserAccount, ActionError> {
+    match username {
+        Some(username) => Ok(Database::get(&username)?.ok_or(ActionError::NotFound)?),
+        None => u.user_account(),
+    }
+}
+
+/**
+Parameters: changes  - changes received from the client
+            editable - tells whether the user may change a field
+Return: Result<Vec<ProfileUpdate>, ActionError> - Validated changes
+ **/
+fn validate_changes(
+    changes: Vec<(ProfileField, String)>,
+    editable: impl Fn(ProfileField) -> bool,
+) -> Result<Vec<ProfileUpdate>, ActionError> {
+    changes
+        .iter()
+        .map(|(field, value)| {
+            if !editable(*field) {
+                return Err(ValidationError::NotEditable).field(field.name());
+            }
+            parse_profile_update(*field, value).field(field.name())
+        })
+        .collect()
+}
+
+/**
+Parameters: user       - account to change
+            updates    - validated changes
+            changed_by - user making the changes, recorded in the history of the account
+Return: Result<(), ActionError> - Saves the account, the manager must be an existing account that
+        doesn't report to the user, directly or not
+ **/
+fn apply_changes(user: &mut UserAccount, updates: Vec<ProfileUpdate>, changed_by: &Username) -> Result<(), ActionError> {
+    for update in updates {
+        let update = match update {
+            ProfileUpdate::Manager(Some(manager)) => {
+                let manager = Database::get(&manager)?.ok_or(ActionError::NotFound)?;
+                if Hierarchy::load()?.creates_cycle(user.username(), &manager) {
+                    return Err(ValidationError::InvalidManager).field("manager");
+                }
+                // The username is stored as written in the manager's account
+                ProfileUpdate::Manager(Some(manager.username().clone()))
+            }
+            update => update,
+        };
+        user.update_profile(update, changed_by);
+    }
+    Database::insert(user)?;
+    Ok(())
+}
+
+impl ActionHandler for AddUser {
+    const OBJECT: &'static str = "add_user";
+    type Request = AddUserRequest;
+    type Input = (Username, Password, PhoneNumber, UserRole);
+    type Output = Response;
+
+    fn validate(request: AddUserRequest, _u: &ConnectedUser) -> Result<Self::Input, ActionError> {
+        let username = Username::parse(&request.username).field("username")?;
+        if is_reserved_username(&username) {
+            return Err(ValidationError::ReservedUsername).field("username");
+        }
+        let password = Password::parse_new(&request.password, &username).field("password")?;
+        let phone = PhoneNumber::parse(&request.phone_number).field("phone_number")?;
+        Ok((username, password, phone, request.role))
+    }
+
+    fn execute(
+        (username, password, phone, role): Self::Input,
+        u: &mut ConnectedUser,
+    ) -> Result<Response, ActionError> {
+        check_username_available(&username, u)?;
+
+        let salt = generate_salt();
+        let user = match generate_hash(&password, &salt) {
+            Ok((hash_password, pepper_version)) => UserAccount::new(username, hash_password, pepper_version, phone, role),
+            Err(e) => {
+                error!("Cannot hash the password of new user {}: {}", username, e);
+                return Err(ActionError::Internal);
+            }
+        };
+
+        // HR accounts are only created once a second HR user approves them
+        if role == UserRole::HR {
+            return request_approval(Operation::AddUser(Box::new(user)), u);
+        }
+        Database::insert(&user)?;
+        info!("User {} added in database from {}", user.username(), u.display_name());
+        Ok(Response::Done)
+    }
+}
+
+impl ActionHandler for ChangeRole {
+    const OBJECT: &'static str = "change_role";
+    type Request = ChangeRoleRequest;
+    type Input = (Username, UserRole);
+    type Output = Response;
+
+    fn validate(request: ChangeRoleRequest, _u: &ConnectedUser) -> Result<(Username, UserRole), ActionError> {
+        Ok((Username::parse(&request.username).field("username")?, request.role))
+    }
+
+    fn execute((username, role): (Username, UserRole), u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let user = Database::get(&username)?.ok_or(ActionError::NotFound)?;
+        if *user.role() == role {
+            return Err(ActionError::Conflict);
+        }
+        request_approval(Operation::ChangeRole(user.username().clone(), role), u)
+    }
+}
+
+impl ActionHandler for DeleteUser {
+    const OBJECT: &'static str = "delete_user";
+    type Request = DeleteUserRequest;
+    type Input = Username;
+    type Output = Response;
+
+    fn validate(request: DeleteUserRequest, _u: &ConnectedUser) -> Result<Username, ActionError> {
+        Username::parse(&request.username).field("username")
+    }
+
+    fn execute(username: Username, u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let user = Database::get(&username)?.ok_or(ActionError::NotFound)?;
+        request_approval(Operation::DeleteUser(user.username().clone()), u)
+    }
+}
+
+impl ActionHandler for ListPending {
+    const OBJECT: &'static str = "list_pending";
+    type Request = ();
+    type Input = ();
+    type Output = Response;
+
+    fn validate(_request: (), _u: &ConnectedUser) -> Result<(), ActionError> {
+        Ok(())
+    }
+
+    fn execute(_input: (), _u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        Ok(Response::Pending(Database::pending()?.iter().map(PendingView::new).collect()))
+    }
+}
+
+impl ActionHandler for Approve {
+    const OBJECT: &'static str = "approve";
+    type Request = PendingDecisionRequest;
+    type Input = u32;
+    type Output = ();
+
+    fn validate(request: PendingDecisionRequest, _u: &ConnectedUser) -> Result<u32, ActionError> {
+        Ok(request.id)
+    }
+
+    fn execute(id: u32, u: &mut ConnectedUser) -> Result<(), ActionError> {
+        let approver = u.account_name().cloned().ok_or(ActionError::Unauthorized)?;
+        let request = Database::get_pending(id)?.ok_or(ActionError::NotFound)?;
+        if request.is_requested_by(&approver) {
+            warn!("{} tried to approve their own request {}", u.display_name(), id);
+            return Err(ActionError::Unauthorized);
+        }
+        if request.targets(&approver) {
+            warn!("{} tried to approve request {} about their own account", u.display_name(), id);
+            return Err(ActionError::Unauthorized);
+        }
+
+        // Taken before being applied, so that two approvals don't apply it twice
+        let request = Database::take_pending(id)?.ok_or(ActionError::NotFound)?;
+        let username = request.operation.username().clone();
+        if let Err(e) = apply_operation(request.operation.clone(), u) {
+            // The request stays pending, it can still be rejected or approved once possible
+            Database::restore_pending(request)?;
+            return Err(e);
+        }
+        info!("Request {} about user {} approved by {}", id, username, u.display_name());
+        Ok(())
+    }
+}
+
+impl ActionHandler for Reject {
+    const OBJECT: &'static str = "reject";
+    type Request = PendingDecisionRequest;
+    type Input = u32;
+    type Output = ();
+
+    fn validate(request: PendingDecisionRequest, _u: &ConnectedUser) -> Result<u32, ActionError> {
+        Ok(request.id)
+    }
+
+    fn execute(id: u32, u: &mut ConnectedUser) -> Result<(), ActionError> {
+        let decider = u.account_name().cloned().ok_or(ActionError::Unauthorized)?;
+        // The author can withdraw a request, but the targeted user can't escape it
+        let request = Database::get_pending(id)?.ok_or(ActionError::NotFound)?;
+        if request.targets(&decider) {
+            warn!("{} tried to reject request {} about their own account", u.display_name(), id);
+            return Err(ActionError::Unauthorized);
+        }
+
+        let request = Database::take_pending(id)?.ok_or(ActionError::NotFound)?;
+        info!("Request {} about user {} rejected by {}", id, request.operation.username(), u.display_name());
+        Ok(())
+    }
+}
+
+impl ActionHandler for Register {
+    const OBJECT: &'static str = "register";
+    type Request = RegisterRequest;
+    type Input = (Username, Password, PhoneNumber);
+    type Output = Response;
+
+    fn validate(request: RegisterRequest, u: &ConnectedUser) -> Result<Self::Input, ActionError> {
+        // Every attempt counts, also the refused ones, to slow down scripts trying usernames
+        if let Err(retry_after) = REGISTRATIONS.check(u.peer_ip()) {
+            warn!("Too many registrations from {:?}", u.peer_ip());
+            return Err(ActionError::RateLimited { retry_after });
+        }
+
+        let username = Username::parse(&request.username).field("username")?;
+        if is_reserved_username(&username) {
+            return Err(ValidationError::ReservedUsername).field("username");
+        }
+        let password = Password::parse_new(&request.password, &username).field("password")?;
+        let phone = PhoneNumber::parse(&request.phone_number).field("phone_number")?;
+        Ok((username, password, phone))
+    }
+
+    fn execute((username, password, phone): Self::Input, u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        check_username_available(&username, u)?;
+
+        let salt = generate_salt();
+        let user = match generate_hash(&password, &salt) {
+            Ok((hash_password, pepper_version)) => {
+                UserAccount::new(username, hash_password, pepper_version, phone, UserRole::StandardUser)
+            }
+            Err(e) => {
+                error!("Cannot hash the password of registered user {}: {}", username, e);
+                return Err(ActionError::Internal);
+            }
+        };
+
+        let username = user.username().clone();
+        let id = Database::add_pending(Operation::Register(Box::new(user)), None)?;
+        info!("Registration {} of user {} submitted from {:?}", id, username, u.peer_ip());
+        Ok(Response::PendingApproval(id))
+    }
+}
+
+impl ActionHandler for CreateInvite {
+    const OBJECT: &'static str = "create_invite";
+    type Request = CreateInviteRequest;
+    type Input = (Username, UserRole);
+    type Output = Response;
+
+    fn validate(request: CreateInviteRequest, _u: &ConnectedUser) -> Result<(Username, UserRole), ActionError> {
+        let username = Username::parse(&request.username).field("username")?;
+        if is_reserved_username(&username) {
+            return Err(ValidationError::ReservedUsername).field("username");
+        }
+        Ok((username, request.role))
+    }
+
+    fn execute((username, role): (Username, UserRole), u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let created_by = u.account_name().cloned().ok_or(ActionError::Unauthorized)?;
+        check_username_available(&username, u)?;
+
+        let code = generate_invite_code();
+        let expires_at = Utc::now() + Duration::hours(CONFIG.invite_validity_hours);
+        Database::add_invitation(Invitation {
+            code_digest: invite_code_digest(&code),
+            username: username.clone(),
+            role,
+            created_by,
+            expires_at,
+        })?;
+        info!("Invitation of user {} as {:?} created by {}", username, role, u.display_name());
+        Ok(Response::Invitation { code, expires_at })
+    }
+}
+
+impl ActionHandler for RedeemInvite {
+    const OBJECT: &'static str = "redeem_invite";
+    type Request = RedeemInviteRequest;
+    type Input = (InviteCode, String, PhoneNumber);
+    type Output = Response;
+
+    fn validate(request: RedeemInviteRequest, _u: &ConnectedUser) -> Result<Self::Input, ActionError> {
+        let code = InviteCode::parse(&request.code).field("code")?;
+        // The policy is checked once the username of the invitation is known
+        Password::parse(&request.password).field("password")?;
+        let phone = PhoneNumber::parse(&request.phone_number).field("phone_number")?;
+        Ok((code, request.password, phone))
+    }
+
+    fn execute((code, password, phone): Self::Input, u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let digest = invite_code_digest(&code);
+        let invitation = match Database::get_invitation(&digest)? {
+            Some(invitation) => invitation,
+            None => {
+                warn!("Unknown or expired invitation code used by {}", u.display_name());
+                return Err(ActionError::NotFound);
+            }
+        };
+        let password = Password::parse_new(&password, &invitation.username).field("password")?;
+
+        // Taken before creating the account, so that the code can only be used once
+        let invitation = Database::take_invitation(&digest)?.ok_or(ActionError::NotFound)?;
+        match create_invited_account(&invitation, &password, phone, u) {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                warn!("Cannot create the invited account {}: {}", invitation.username, e);
+                // The code can be used again once the problem is solved, e.g. a username conflict
+                Database::add_invitation(invitation)?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/**
+Parameters: invitation - invitation taken from the database
+            password   - password chosen by the new user, already checked against the policy
+            phone      - phone number of the new user
+            u          - connected user redeeming the invitation
+Return: Result<Response, ActionError> - Creates the account, or asks for an approval for an HR account
+ **/
+fn create_invited_account(
+    invitation: &Invitation,
+    password: &Password,
+    phone: PhoneNumber,
+    u: &ConnectedUser,
+) -> Result<Response, ActionError> {
+    check_username_available(&invitation.username, u)?;
+
+    let salt = generate_salt();
+    let user = match generate_hash(password, &salt) {
+        Ok((hash_password, pepper_version)) => {
+            UserAccount::new(invitation.username.clone(), hash_password, pepper_version, phone, invitation.role)
+        }
+        Err(e) => {
+            error!("Cannot hash the password of invited user {}: {}", invitation.username, e);
+            return Err(ActionError::Internal);
+        }
+    };
+
+    // HR accounts still need a second HR user, the approval is asked on behalf of the inviter
+    if invitation.role == UserRole::HR {
+        let id = Database::add_pending(Operation::AddUser(Box::new(user)), Some(&invitation.created_by))?;
+        info!("Invitation of user {} redeemed, request {} waits for an approval", invitation.username, id);
+        return Ok(Response::PendingApproval(id));
+    }
+    Database::insert(&user)?;
+    info!("Invitation of user {} redeemed", invitation.username);
+    Ok(Response::Done)
+}
+
+impl ActionHandler for ListMySessions {
+    const OBJECT: &'static str = "list_my_sessions";
+    type Request = ();
+    type Input = ();
+    type Output = Response;
+
+    fn validate(_request: (), _u: &ConnectedUser) -> Result<(), ActionError> {
+        Ok(())
+    }
+
+    fn execute(_input: (), u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        let username = u.account_name().ok_or(ActionError::Unauthorized)?;
+        Ok(Response::Sessions(session::list(u.session_id(), Some(username))))
+    }
+}
+
+impl ActionHandler for ListSessions {
+    const OBJECT: &'static str = "list_sessions";
+    type Request = ();
+    type Input = ();
+    type Output = Response;
+
+    fn validate(_request: (), _u: &ConnectedUser) -> Result<(), ActionError> {
+        Ok(())
+    }
+
+    fn execute(_input: (), u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        Ok(Response::Sessions(session::list(u.session_id(), None)))
+    }
+}
+
+impl ActionHandler for TerminateSession {
+    const OBJECT: &'static str = "terminate_session";
+    type Request = TerminateSessionRequest;
+    type Input = u64;
+    type Output = ();
+
+    fn validate(request: TerminateSessionRequest, u: &ConnectedUser) -> Result<u64, ActionError> {
+        // Logout or Exit end the current session
+        if request.id == u.session_id() {
+            return Err(ValidationError::CurrentSession).field("session");
+        }
+        Ok(request.id)
+    }
+
+    fn execute(id: u64, u: &mut ConnectedUser) -> Result<(), ActionError> {
+        // Users can only end their own sessions, the others are reported as not found
+        if u.viewer()? != Viewer::HR {
+            let own = session::username(id)
+                .zip(u.account_name())
+                .is_some_and(|(owner, username)| username_key(&owner) == username_key(username));
+            if !own {
+                return Err(ActionError::NotFound);
+            }
+        }
+
+        if !session::terminate(id) {
+            return Err(ActionError::NotFound);
+        }
+        info!("Session {} terminated by {}", id, u.display_name());
+        Ok(())
+    }
+}
+
+/**
+Parameters: username - username of a new account
+            u        - connected user creating the account
+Return: Result<(), ActionError> - Conflict if the username, or one that looks like it, is already
+        used by an account, a pending creation or an invitation
+ **/
+fn check_username_available(username: &Username, u: &ConnectedUser) -> Result<(), ActionError> {
+    if Database::get(username)?.is_some() {
+        return Err(ActionError::Conflict);
+    }
+    let pending: Vec<Username> = Database::pending()?
+        .into_iter()
+        .filter_map(|request| match request.operation {
+            Operation::AddUser(user) | Operation::Register(user) => Some(user.username().clone()),
+            _ => None,
+        })
+        .collect();
+    let invited: Vec<Username> = Database::invitations()?.into_iter().map(|invitation| invitation.username).collect();
+    let existing: Vec<Username> = Database::values()?.into_iter().map(|user| user.username().clone()).collect();
+
+    let confusable = existing
+        .iter()
+        .chain(pending.iter())
+        .chain(invited.iter())
+        .find(|existing| username_key(existing) == username_key(username) || are_confusable(existing, username));
+    if let Some(existing) = confusable {
+        warn!("Username {} looks like {}, refused from {}", username, existing, u.display_name());
+        return Err(ActionError::Conflict);
+    }
+    Ok(())
+}
+
+/**
+Parameters: operation - sensitive operation asked by an HR user
+            u         - connected user asking for it
+Return: Result<Response, ActionError> - Number of the request, which waits for another HR user
+ **/
+fn request_approval(operation: Operation, u: &mut ConnectedUser) -> Result<Response, ActionError> {
+    let requested_by = u.account_name().cloned().ok_or(ActionError::Unauthorized)?;
+    let username = operation.username().clone();
+    let id = Database::add_pending(operation, Some(&requested_by))?;
+    info!("Request {} about user {} submitted by {}", id, username, u.display_name());
+    Ok(Response::PendingApproval(id))
+}
+
+/**
+Parameters: operation - approved operation
+            u         - connected user who approved it
+Return: Result<(), ActionError> - Applies the operation, which may have become impossible since
+        it was requested
+ **/
+fn apply_operation(operation: Operation, u: &ConnectedUser) -> Result<(), ActionError> {
+    let approver = u.account_name().ok_or(ActionError::Unauthorized)?;
+    match operation {
+        Operation::AddUser(user) | Operation::Register(user) => {
+            check_username_available(user.username(), u)?;
+            Database::insert(&user)?;
+            info!("User {} added in database from {}", user.username(), u.display_name());
+        }
+        Operation::ChangeRole(username, role) => {
+            let mut user = Database::get(&username)?.ok_or(ActionError::NotFound)?;
+            user.change_role(role, approver);
+            Database::insert(&user)?;
+            info!("Role of user {} changed to {:?} from {}", username, role, u.display_name());
+        }
+        Operation::DeleteUser(username) => {
+            let mut user = Database::get(&username)?.ok_or(ActionError::NotFound)?;
+            user.record_deletion(approver);
+            Database::remove(&user)?.ok_or(ActionError::NotFound)?;
+            info!("User {} deleted from {}", username, u.display_name());
+        }
+    }
+    Ok(())
+}
+
+impl ActionHandler for Login {
+    const OBJECT: &'static str = "login";
+    type Request = LoginRequest;
+    type Input = (Username, Password);
+    type Output = ();
+
+    fn validate(request: LoginRequest, _u: &ConnectedUser) -> Result<(Username, Password), ActionError> {
+        let username = Username::parse(&request.username).field("username")?;
+        Ok((username, Password::parse(&request.password).field("password")?))
+    }
+
+    fn execute(
+        (username, password): (Username, Password),
+        u: &mut ConnectedUser,
+    ) -> Result<(), ActionError> {
+        let mut user = match Database::get(&username)? {
+            Some(user) => user,
+            None => {
+                // Same work as for an existing user, the response time doesn't reveal the username
+                dummy_verify(&password);
+                warn!("Invalid inputs for username : {}", username);
+                return Err(ActionError::Unauthorized);
+            }
+        };
+
+        match verify_hash(user.password(), user.pepper_version(), &password) {
+            Ok(true) => {}
+            Ok(false) => {
+                warn!("Invalid inputs for username : {}", username);
+                return Err(ActionError::Unauthorized);
+            }
+            Err(e) => {
+                error!("Corrupted password hash for account {}: {}", username, e);
+                return Err(ActionError::Internal);
+            }
+        }
+
+        if needs_rehash(user.password(), user.pepper_version()) {
+            let salt = generate_salt();
+            match generate_hash(&password, &salt) {
+                Ok((hash_password, pepper_version)) => {
+                    user.set_password(hash_password, pepper_version);
+                    Database::insert(&user)?;
+                    info!("Password hash of {} upgraded to the current parameters", username);
+                }
+                Err(e) => error!("Cannot upgrade the password hash of {}: {}", username, e),
+            }
+        }
+        if user.password_changed().is_none() {
+            user.start_password_tracking();
+            Database::insert(&user)?;
+        }
+        u.set_username(user.username());
+        if password_expiry(&user).is_some_and(|expiry| expiry <= Utc::now()) {
+            warn!("{} has logged in with an expired password", username);
+            u.set_password_expired(true);
+        }
+        info!("{} has logged in", username);
+        Ok(())
+    }
+}
+
+impl ActionHandler for Logout {
+    const OBJECT: &'static str = "logout";
+    const ALLOWED_WITH_EXPIRED_PASSWORD: bool = true;
+    type Request = ();
+    type Input = ();
+    type Output = ();
+
+    fn validate(_request: (), _u: &ConnectedUser) -> Result<(), ActionError> {
+        Ok(())
+    }
+
+    fn execute(_input: (), u: &mut ConnectedUser) -> Result<(), ActionError> {
+        u.logout();
+        Ok(())
+    }
+}
+
+impl ActionHandler for ChangePassword {
+    const OBJECT: &'static str = "change_password";
+    const ALLOWED_WITH_EXPIRED_PASSWORD: bool = true;
+    type Request = ChangePasswordRequest;
+    type Input = (Password, Password);
+    type Output = ();
+
+    fn validate(request: ChangePasswordRequest, u: &ConnectedUser) -> Result<(Password, Password), ActionError> {
+        let username = u.account_name().ok_or(ActionError::Unauthorized)?;
+        let old_password = Password::parse(&request.old_password).field("old_password")?;
+        Ok((old_password, Password::parse_new(&request.new_password, username).field("new_password")?))
+    }
+
+    fn execute(
+        (old_password, new_password): (Password, Password),
+        u: &mut ConnectedUser,
+    ) -> Result<(), ActionError> {
+        let mut user = u.user_account()?;
+        match verify_hash(user.password(), user.pepper_version(), &old_password) {
+            Ok(true) => {}
+            Ok(false) => return Err(ActionError::Unauthorized),
+            Err(e) => {
+                error!("Corrupted password hash for account {}: {}", user.username(), e);
+                return Err(ActionError::Internal);
+            }
+        }
+
+        if is_reused_password(&user, &new_password) {
+            let rejection = ValidationError::PasswordRejected(vec![PasswordRejection::Reused]);
+            return Err(rejection).field("new_password");
+        }
+
+        let salt = generate_salt();
+        match generate_hash(&new_password, &salt) {
+            Ok((hash_password, pepper_version)) => {
+                let history_size = CONFIG.password_policy.history_size;
+                user.change_password(hash_password, pepper_version, history_size);
+                Database::insert(&user)?;
+                u.set_password_expired(false);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Cannot hash the new password of {}: {}", user.username(), e);
+                Err(ActionError::Internal)
+            }
+        }
+    }
+}
+
+/// Validated search, the cursor is the sort key of the last user of the previous page
+pub struct Search {
+    query: SearchQuery,
+    mode: MatchMode,
+    role: Option<UserRole>,
+    sort: SortField,
+    descending: bool,
+    after: Option<(u8, String)>,
+    page_size: usize,
+}
+
+impl ActionHandler for SearchUsers {
+    const OBJECT: &'static str = "search_users";
+    type Request = SearchUsersRequest;
+    type Input = Search;
+    type Output = Response;
+
+    fn validate(request: SearchUsersRequest, _u: &ConnectedUser) -> Result<Search, ActionError> {
+        let after = match &request.cursor {
+            Some(cursor) => Some(decode_cursor(cursor).ok_or(ValidationError::InvalidCursor).field("cursor")?),
+            None => None,
+        };
+
+        Ok(Search {
+            query: SearchQuery::parse(&request.query).field("query")?,
+            mode: request.mode,
+            role: request.role,
+            sort: request.sort,
+            descending: request.descending,
+            after,
+            page_size: parse_page_size(request.page_size).field("page_size")?,
+        })
+    }
+
+    fn execute(search: Search, u: &mut ConnectedUser) -> Result<Response, ActionError> {
+        // Filtering or sorting by role would reveal the roles to users who can't see them
+        let viewer = u.viewer()?;
+        if !viewer.can_see_roles() && (search.role.is_some() || search.sort == SortField::Role) {
+            return Err(ActionError::Unauthorized);
+        }
+
+        let users: Vec<((u8, String), UserAccount)> = Database::values()?
+            .into_iter()
+            .filter(|user| search.role.is_none_or(|role| *user.role() == role))
+            .filter(|user| {
+                let key = username_key(user.username());
+                match search.mode {
+                    MatchMode::Prefix => key.starts_with(&*search.query),
+                    MatchMode::Substring => key.contains(&*search.query),
+                }
+            })
+            .map(|user| (sort_key(&user, search.sort), user))
+            .collect();
+
+        let (users, next_cursor) = keyset_page(users, search.after.as_ref(), search.descending, search.page_size);
+        let users = users.iter().map(|user| UserView::new(user, viewer)).collect();
+
+        Ok(Response::UserPage { users, next_cursor })
+    }
+}
+
+/**
+Parameters: users      - matching users with their sort key
+            after      - sort key of the last user of the previous page, None for the first page
+            descending - whether the page is sorted from the largest key
+            page_size  - maximum number of users in the page
+Return: (Vec<T>, Option<String>) - Users of the page, with the cursor of the next page if there is one
+ **/
+fn keyset_page<T>(
+    users: Vec<((u8, String), T)>,
+    after: Option<&(u8, String)>,
+    descending: bool,
+    page_size: usize,
+) -> (Vec<T>, Option<String>) {
+    let mut users: Vec<((u8, String), T)> = users
+        .into_iter()
+        .filter(|(key, _)| match after {
+            Some(after) if descending => key < after,
+            Some(after) => key > after,
+            None => true,
+        })
+        .collect();
+
+    users.sort_by(|(a, _), (b, _)| if descending { b.cmp(a) } else { a.cmp(b) });
+
+    // One more user than asked tells whether there is a next page
+    let next_cursor = if users.len() > page_size {
+        Some(encode_cursor(&users[page_size - 1].0))
+    } else {
+        None
+    };
+    let users = users.into_iter().take(page_size).map(|(_, user)| user).collect();
+    (users, next_cursor)
+}
+
+/**
+Parameters: user - account to sort
+            sort - field used to sort the results
+Return: (u8, String) - Key sorting the accounts, unique thanks to the username key
+ **/
+fn sort_key(user: &UserAccount, sort: SortField) -> (u8, String) {
+    let rank = match (sort, user.role()) {
+        (SortField::Username, _) => 0,
+        (SortField::Role, UserRole::HR) => 1,
+        (SortField::Role, UserRole::StandardUser) => 2,
+    };
+    (rank, username_key(user.username()))
+}
+
+/**
+Parameter: key - sort key of the last user of a page
+Return: String - Cursor sent to the client
+ **/
+fn encode_cursor((rank, username): &(u8, String)) -> String {
+    format!("{}:{}", rank, username)
+}
+
+/**
+Parameter: cursor - cursor received from the client
+Return: Option<(u8, String)> - Sort key after which the next page starts, None if the cursor is invalid
+ **/
+fn decode_cursor(cursor: &str) -> Option<(u8, String)> {
+    let (rank, username) = cursor.split_once(':')?;
+    let username = Username::parse(username).ok()?;
+    Some((rank.parse().ok()?, username_key(&username)))
+}
+
+/**
+Parameters: user     - account changing its password
+            password - new password
+Return: Bool - True if the password is the current one or one of the previous ones
+ **/
+fn is_reused_password(user: &UserAccount, password: &str) -> bool {
+    let previous = user
+        .password_history()
+        .iter()
+        .map(|old| (old.hash.as_str(), old.pepper_version));
+
+    std::iter::once((user.password(), user.pepper_version()))
+        .chain(previous)
+        .any(|(hash, pepper_version)| match verify_hash(hash, pepper_version, password) {
+            Ok(matches) => matches,
+            Err(e) => {
+                // An old hash that can't be checked anymore doesn't block the change
+                warn!("Cannot check a previous password of {}: {}", user.username(), e);
+                false
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users(names: &[&'static str]) -> Vec<((u8, String), &'static str)> {
+        names.iter().map(|name| ((0, name.to_string()), *name)).collect()
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let key = (2, "alice".to_string());
+        assert_eq!(decode_cursor(&encode_cursor(&key)), Some(key));
+    }
+
+    #[test]
+    fn cursor_key_is_case_insensitive() {
+        assert_eq!(decode_cursor("1:Alice"), Some((1, "alice".to_string())));
+    }
+
+    #[test]
+    fn invalid_cursors_are_refused() {
+        assert_eq!(decode_cursor("alice"), None);
+        assert_eq!(decode_cursor("x:alice"), None);
+        assert_eq!(decode_cursor("256:alice"), None);
+        assert_eq!(decode_cursor("0:"), None);
+        assert_eq!(decode_cursor("0:a\"b"), None);
+    }
+
+    #[test]
+    fn ascending_pages_follow_each_other() {
+        let all = users(&["dave", "alice", "carol", "bob", "eve"]);
+        let (page, cursor) = keyset_page(all.clone(), None, false, 2);
+        assert_eq!(page, ["alice", "bob"]);
+
+        let after = decode_cursor(&cursor.unwrap()).unwrap();
+        let (page, cursor) = keyset_page(all.clone(), Some(&after), false, 2);
+        assert_eq!(page, ["carol", "dave"]);
+
+        let after = decode_cursor(&cursor.unwrap()).unwrap();
+        let (page, cursor) = keyset_page(all, Some(&after), false, 2);
+        assert_eq!(page, ["eve"]);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn descending_pages_follow_each_other() {
+        let all = users(&["dave", "alice", "carol", "bob", "eve"]);
+        let (page, cursor) = keyset_page(all.clone(), None, true, 2);
+        assert_eq!(page, ["eve", "dave"]);
+
+        let after = decode_cursor(&cursor.unwrap()).unwrap();
+        let (page, cursor) = keyset_page(all.clone(), Some(&after), true, 2);
+        assert_eq!(page, ["carol", "bob"]);
+
+        let after = decode_cursor(&cursor.unwrap()).unwrap();
+        let (page, cursor) = keyset_page(all, Some(&after), true, 2);
+        assert_eq!(page, ["alice"]);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn full_last_page_has_no_cursor() {
+        let (page, cursor) = keyset_page(users(&["alice", "bob"]), None, false, 2);
+        assert_eq!(page, ["alice", "bob"]);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn role_rank_sorts_before_the_username() {
+        let all = vec![
+            ((2, "alice".to_string()), "alice"),
+            ((1, "zoe".to_string()), "zoe"),
+            ((2, "bob".to_string()), "bob"),
+        ];
+        let (page, cursor) = keyset_page(all.clone(), None, true, 1);
+        assert_eq!(page, ["bob"]);
+
+        let after = decode_cursor(&cursor.unwrap()).unwrap();
+        let (page, _) = keyset_page(all, Some(&after), true, 5);
+        assert_eq!(page, ["alice", "zoe"]);
+    }
+}
@@ -0,0 +1,203 @@
+/// This file is used to follow the reporting lines between the accounts, each account references
+/// its manager in its profile
+use crate::database::Database;
+use crate::user::UserAccount;
+use crate::validate_inputs::{username_key, Username};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+/// Snapshot of the accounts of the database, indexed to follow the managers
+pub struct Hierarchy {
+    users: HashMap<String, UserAccount>,
+}
+
+impl Hierarchy {
+    pub fn load() -> Result<Hierarchy, Box<dyn Error>> {
+        Ok(Hierarchy::new(Database::values()?))
+    }
+
+    pub fn new(users: Vec<UserAccount>) -> Hierarchy {
+        let users = users.into_iter().map(|user| (username_key(user.username()), user)).collect();
+        Hierarchy { users }
+    }
+
+    /**
+    Parameter: user - account whose manager is wanted
+    Return: Option<&UserAccount> - Manager of the account, None if it has none or it doesn't exist anymore
+     **/
+    pub fn manager_of(&self, user: &UserAccount) -> Option<&UserAccount> {
+        self.users.get(&username_key(user.manager()?))
+    }
+
+    /**
+    Parameter: user - account whose managers are wanted
+    Return: Vec<&UserAccount> - Managers from the direct one up to the top of the organisation.
+            The chain stops before a loop, in case one was stored before the loops were refused
+     **/
+    pub fn chain_of_command(&self, user: &UserAccount) -> Vec<&UserAccount> {
+        let mut seen = HashSet::from([username_key(user.username())]);
+        let mut chain = Vec::new();
+        let mut current = user;
+        while let Some(manager) = self.manager_of(current) {
+            if !seen.insert(username_key(manager.username())) {
+                break;
+            }
+            chain.push(manager);
+            current = manager;
+        }
+        chain
+    }
+
+    /**
+    Parameter: user - manager whose reports are wanted
+    Return: Vec<&UserAccount> - Accounts managed directly by the user, sorted by username
+     **/
+    pub fn direct_reports(&self, user: &UserAccount) -> Vec<&UserAccount> {
+        let key = username_key(user.username());
+        let mut reports: Vec<&UserAccount> = self
+            .users
+            .values()
+            .filter(|report| report.manager().is_some_and(|manager| username_key(manager) == key))
+            .collect();
+        reports.sort_by_key(|report| username_key(report.username()));
+        reports
+    }
+
+    /**
+    Parameters: username - account whose manager changes
+                manager  - new manager
+    Return: Bool - True if the user would end up managing themselves, directly or not
+     **/
+    pub fn creates_cycle(&self, username: &Username, manager: &UserAccount) -> bool {
+        let key = username_key(username);
+        username_key(manager.username()) == key
+            || self
+                .chain_of_command(manager)
+                .iter()
+                .any(|above| username_key(above.username()) == key)
+    }
+
+    /**
+    Parameter: None
+    Return: String - Org chart in the DOT format of Graphviz, with an edge from each manager to
+            each of their reports, e.g. `dot -Tsvg org_chart.dot -o org_chart.svg`
+     **/
+    pub fn to_dot(&self) -> String {
+        let mut users: Vec<&UserAccount> = self.users.values().collect();
+        users.sort_by_key(|user| username_key(user.username()));
+
+        let mut dot = String::from("digraph org_chart {\n    rankdir=TB;\n    node [shape=box];\n");
+        for user in &users {
+            dot.push_str(&format!("    {};\n", dot_id(user.username())));
+        }
+        for user in &users {
+            if let Some(manager) = self.manager_of(user) {
+                dot.push_str(&format!("    {} -> {};\n", dot_id(manager.username()), dot_id(user.username())));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Quoted DOT identifier, the accounts stored before the username policy may contain quotes or backslashes
+fn dot_id(username: &str) -> String {
+    format!("\"{}\"", username.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user::{ProfileUpdate, UserRole};
+    use crate::validate_inputs::PhoneNumber;
+
+    fn username(name: &str) -> Username {
+        Username::parse(name).unwrap()
+    }
+
+    // Accounts given as (username, manager)
+    fn hierarchy(users: &[(&str, Option<&str>)]) -> Hierarchy {
+        let users = users
+            .iter()
+            .map(|(name, manager)| {
+                let mut user = UserAccount::new(
+                    username(name),
+                    String::new(),
+                    None,
+                    PhoneNumber::parse("+41784539872").unwrap(),
+                    UserRole::StandardUser,
+                );
+                let manager = ProfileUpdate::Manager(manager.map(username));
+                user.update_profile(manager, &username("admin"));
+                user
+            })
+            .collect();
+        Hierarchy::new(users)
+    }
+
+    fn names(users: Vec<&UserAccount>) -> Vec<&str> {
+        users.iter().map(|user| &**user.username()).collect()
+    }
+
+    fn account<'a>(hierarchy: &'a Hierarchy, name: &str) -> &'a UserAccount {
+        &hierarchy.users[&username_key(name)]
+    }
+
+    #[test]
+    fn chain_goes_up_to_the_top() {
+        let h = hierarchy(&[("alice", None), ("bob", Some("alice")), ("carol", Some("Bob"))]);
+        assert_eq!(names(h.chain_of_command(account(&h, "carol"))), ["bob", "alice"]);
+        assert!(h.chain_of_command(account(&h, "alice")).is_empty());
+    }
+
+    #[test]
+    fn chain_stops_at_a_missing_manager() {
+        let h = hierarchy(&[("bob", Some("alice")), ("carol", Some("bob"))]);
+        assert_eq!(names(h.chain_of_command(account(&h, "carol"))), ["bob"]);
+    }
+
+    #[test]
+    fn chain_stops_before_a_stored_loop() {
+        let h = hierarchy(&[("alice", Some("carol")), ("bob", Some("alice")), ("carol", Some("bob"))]);
+        assert_eq!(names(h.chain_of_command(account(&h, "carol"))), ["bob", "alice"]);
+
+        let h = hierarchy(&[("alice", Some("alice")), ("bob", Some("alice"))]);
+        assert_eq!(names(h.chain_of_command(account(&h, "bob"))), ["alice"]);
+    }
+
+    #[test]
+    fn org_chart_has_an_edge_per_manager() {
+        let h = hierarchy(&[("alice", None), ("bob", Some("alice"))]);
+        let dot = h.to_dot();
+        assert!(dot.contains("    \"alice\";\n    \"bob\";\n"));
+        assert!(dot.contains("    \"alice\" -> \"bob\";\n"));
+    }
+
+    #[test]
+    fn stored_usernames_are_escaped() {
+        // Accounts stored before the username policy are loaded without being validated
+        let stored: Username = serde_json::from_str(r#""a\\b\"c""#).unwrap();
+        assert_eq!(dot_id(&stored), r#""a\\b\"c""#);
+    }
+
+    #[test]
+    fn self_manager_is_a_cycle() {
+        let h = hierarchy(&[("alice", None)]);
+        assert!(h.creates_cycle(&username("Alice"), account(&h, "alice")));
+    }
+
+    #[test]
+    fn indirect_loop_is_a_cycle() {
+        let h = hierarchy(&[("alice", None), ("bob", Some("alice")), ("carol", Some("bob"))]);
+        assert!(h.creates_cycle(&username("alice"), account(&h, "carol")));
+        assert!(h.creates_cycle(&username("bob"), account(&h, "carol")));
+        assert!(!h.creates_cycle(&username("carol"), account(&h, "alice")));
+    }
+
+    #[test]
+    fn stored_loop_elsewhere_is_not_a_cycle() {
+        let h = hierarchy(&[("alice", Some("bob")), ("bob", Some("alice")), ("carol", None)]);
+        assert!(!h.creates_cycle(&username("carol"), account(&h, "alice")));
+        assert!(h.creates_cycle(&username("alice"), account(&h, "bob")));
+    }
+}
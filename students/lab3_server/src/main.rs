@@ -4,25 +4,38 @@
 /// Tasks todo: - Configure the TLS server properly.
 ///             - Log stuff whenever required
 mod action;
+mod approval;
+mod breach;
+mod config;
 mod connection;
 mod crypto;
 mod database;
+mod handlers;
+mod hierarchy;
+mod rate_limit;
+mod session;
 mod user;
 mod validate_inputs;
 mod access;
 
-use crate::action::{Action, ConnectedUser};
+use crate::action::{password_expiry, Action, ConnectedUser};
+use crate::config::CONFIG;
+use crate::database::Database;
 use crate::user::UserRole;
+use crate::validate_inputs::Username;
+use chrono::Utc;
 use connection::Connection;
 use lazy_static::lazy_static;
 use log::{error, info, warn};
 use native_tls::{Identity, Protocol, TlsAcceptor};
 use rand::Rng;
 use simplelog::{ColorChoice, LevelFilter, TermLogger, TerminalMode};
+use std::env;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
 use std::net::TcpListener;
+use std::process;
 use std::sync::Arc;
 use std::thread;
 
@@ -42,30 +55,50 @@ lazy_static! {
     ];
 }
 
-// Handles client connection by sending a banner and then waiting for a client action
+// Handles client connection by sending a banner and then waiting for a client action.
+// Errors of the actions are sent to the client, only a connection failure or a message that isn't
+// a valid action closes the connection
 fn handle_client(conn: Connection) -> Result<(), Box<dyn Error>> {
     let mut u = ConnectedUser::anonymous(conn); // Anonymous user at first
     loop {
-        let mut banner = "Welcome to RESIGN (hR onlinE uSer dIrectory manaGemeNt)!".to_string();
-        if !u.is_anonymous() {
-            banner.push_str(
-                format!("\nCurrently logged in as {}", u.user_account()?.username()).as_str(),
-            );
-
-            if let UserRole::HR = u.user_account()?.role() {
-                let quote =
-                    MOTIVATIONAL_QUOTES[rand::thread_rng().gen_range(0..MOTIVATIONAL_QUOTES.len())];
-                banner.push_str(format!("\nQuote of the day: {}\n", quote).as_str());
-            }
-        }
-
         // We send the banner to  the client and we expect to receive an Action
+        let banner = banner(&mut u);
         u.conn().send(&banner)?;
         let action = u.conn().receive::<Action>()?;
         action.perform(&mut u)?;
     }
 }
 
+// Builds the banner, the details about the account are left out if it can't be read
+fn banner(u: &mut ConnectedUser) -> String {
+    let mut banner = "Welcome to RESIGN (hR onlinE uSer dIrectory manaGemeNt)!".to_string();
+    let user = match u.user_account() {
+        Ok(user) => user,
+        Err(_) => return banner,
+    };
+
+    banner.push_str(format!("\nCurrently logged in as {}", user.username()).as_str());
+
+    u.check_password_expiry(&user);
+    if u.password_expired() {
+        banner.push_str("\nYour password has expired, please change it before doing anything else");
+    } else if let Some(expiry) = password_expiry(&user) {
+        let days_left = (expiry - Utc::now()).num_days();
+        if days_left < 1 {
+            banner.push_str("\nYour password expires in less than a day, please change it");
+        } else if days_left < CONFIG.password_policy.expiry_warning_days {
+            banner.push_str(format!("\nYour password expires in {} day(s), please change it", days_left).as_str());
+        }
+    }
+
+    if let UserRole::HR = user.role() {
+        let quote =
+            MOTIVATIONAL_QUOTES[rand::thread_rng().gen_range(0..MOTIVATIONAL_QUOTES.len())];
+        banner.push_str(format!("\nQuote of the day: {}\n", quote).as_str());
+    }
+    banner
+}
+
 // Load the server certificate and private key from PKCS8 format
 fn load_server_identity(cert_file: &str, key_file: &str) -> Identity {
     let mut cert = Vec::new();
@@ -93,14 +126,60 @@ fn tls_config(cert_file: &str, key_file: &str) -> Arc<TlsAcceptor> {
     Arc::new(acceptor)
 }
 
+// Promotes a user to HR while there are fewer than two HR accounts, returns the exit code
+fn promote_hr(username: &str) -> i32 {
+    let username = match Username::parse(username) {
+        Ok(username) => username,
+        Err(e) => {
+            error!("Cannot promote {}: {}", username, e);
+            return 2;
+        }
+    };
+    match Database::promote_first_hr(&username) {
+        Ok(()) => {
+            info!("User {} promoted to HR from the server", username);
+            0
+        }
+        Err(e) => {
+            error!("Cannot promote {}: {}", username, e);
+            1
+        }
+    }
+}
+
 fn main() {
-    let _logger = TermLogger::init(
+    TermLogger::init(
         LevelFilter::Trace,
         Default::default(),
         TerminalMode::Stderr,
         ColorChoice::Auto,
     )
     .unwrap();
+    lazy_static::initialize(&CONFIG);
+    crypto::init();
+    validate_inputs::init();
+    if let Err(e) = Database::migrate() {
+        error!("Cannot migrate the stored accounts: {}", e);
+    }
+
+    // One-time bootstrap of the four-eyes principle, run by the operator of the server
+    let args: Vec<String> = env::args().collect();
+    match args.as_slice() {
+        [_] => {}
+        [_, option, username] if option == "--promote-hr" => process::exit(promote_hr(username)),
+        _ => {
+            eprintln!("Usage: {} [--promote-hr <username>]", args[0]);
+            process::exit(2);
+        }
+    }
+    match Database::hr_count() {
+        Ok(count) if count < 2 => warn!(
+            "Only {} HR account(s), the pending requests can't be approved: promote a user with --promote-hr <username>",
+            count
+        ),
+        Ok(_) => {}
+        Err(e) => error!("Cannot count the HR accounts: {}", e),
+    }
     // Start TLS server and wait for new connections
     let acceptor = tls_config(CERT_PATH, KEY_PATH);
     let listener = TcpListener::bind(SERVER_IP).unwrap();
@@ -113,15 +192,14 @@ fn main() {
                 let acceptor = acceptor.clone();
                 thread::spawn(move || {
                     // TLS handshake on top of the connection using the TlsAcceptor
-                    let stream = acceptor.accept(stream);
-                    if stream.is_err() {
-                        error!("TLS handshake failed with error: {}", stream.err().unwrap());
-                    } else {
-                        info!("TLS client connection accepted");
-                        if let Err(e) = handle_client(Connection::new(stream.unwrap())) {
-                            warn!("Connection closed: {}", e);
-                            return;
+                    match acceptor.accept(stream) {
+                        Ok(stream) => {
+                            info!("TLS client connection accepted");
+                            if let Err(e) = handle_client(Connection::new(stream)) {
+                                warn!("Connection closed: {}", e);
+                            }
                         }
+                        Err(e) => error!("TLS handshake failed with error: {}", e),
                     }
                 });
             }
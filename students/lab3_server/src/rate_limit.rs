@@ -0,0 +1,119 @@
+/// This file is used to limit the number of attempts of an action per client address, e.g. to
+/// slow down scripts registering accounts
+use crate::config::{RateLimit, CONFIG};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    pub static ref REGISTRATIONS: RateLimiter = RateLimiter::new(CONFIG.registration_limit.clone());
+}
+
+/// Attempts of the last window per client address, shared by the connection threads
+pub struct RateLimiter {
+    limit: RateLimit,
+    attempts: Mutex<HashMap<Option<IpAddr>, Vec<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> RateLimiter {
+        RateLimiter {
+            limit,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /**
+    Parameter: ip - address of the client, None if it is unknown
+    Return: Result<(), u64> - Ok if the attempt is allowed and recorded, otherwise the number of
+            seconds after which the client can try again
+     **/
+    pub fn check(&self, ip: Option<IpAddr>) -> Result<(), u64> {
+        self.check_at(ip, Instant::now())
+    }
+
+    // Attempt made at the given time, the tests don't have to wait for the window to pass
+    fn check_at(&self, ip: Option<IpAddr>, now: Instant) -> Result<(), u64> {
+        let window = Duration::from_secs(self.limit.window_secs);
+        let mut attempts = self.attempts.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // Forgets the attempts out of the window, and the addresses without any left
+        attempts.retain(|_, times| {
+            times.retain(|time| now.duration_since(*time) < window);
+            !times.is_empty()
+        });
+
+        let times = attempts.entry(ip).or_default();
+        if times.len() >= self.limit.max_attempts {
+            // No attempt at all with a limit of 0
+            let retry_after = times.first().map_or(window, |oldest| window.saturating_sub(now.duration_since(*oldest)));
+            return Err(retry_after.as_secs().max(1));
+        }
+        times.push(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+    const OTHER: Option<IpAddr> = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)));
+
+    fn limiter(max_attempts: usize, window_secs: u64) -> RateLimiter {
+        RateLimiter::new(RateLimit { max_attempts, window_secs })
+    }
+
+    #[test]
+    fn attempts_over_the_limit_are_refused() {
+        let limiter = limiter(2, 60);
+        let start = Instant::now();
+        assert_eq!(limiter.check_at(CLIENT, start), Ok(()));
+        assert_eq!(limiter.check_at(CLIENT, start), Ok(()));
+        assert!(limiter.check_at(CLIENT, start).is_err());
+        // Each address has its own attempts, also an unknown one
+        assert_eq!(limiter.check_at(OTHER, start), Ok(()));
+        assert_eq!(limiter.check_at(None, start), Ok(()));
+    }
+
+    #[test]
+    fn attempts_are_allowed_again_after_the_window() {
+        let limiter = limiter(1, 60);
+        let start = Instant::now();
+        assert_eq!(limiter.check_at(CLIENT, start), Ok(()));
+        assert!(limiter.check_at(CLIENT, start + Duration::from_secs(59)).is_err());
+        assert_eq!(limiter.check_at(CLIENT, start + Duration::from_secs(60)), Ok(()));
+    }
+
+    #[test]
+    fn retry_after_counts_from_the_oldest_attempt() {
+        let limiter = limiter(2, 60);
+        let start = Instant::now();
+        limiter.check_at(CLIENT, start).unwrap();
+        limiter.check_at(CLIENT, start + Duration::from_secs(30)).unwrap();
+        assert_eq!(limiter.check_at(CLIENT, start + Duration::from_secs(40)), Err(20));
+        // Less than a second left is still reported as one
+        assert_eq!(limiter.check_at(CLIENT, start + Duration::from_millis(59_500)), Err(1));
+    }
+
+    #[test]
+    fn refused_attempts_are_not_recorded() {
+        let limiter = limiter(1, 60);
+        let start = Instant::now();
+        limiter.check_at(CLIENT, start).unwrap();
+        assert!(limiter.check_at(CLIENT, start + Duration::from_secs(50)).is_err());
+        assert_eq!(limiter.check_at(CLIENT, start + Duration::from_secs(60)), Ok(()));
+    }
+
+    #[test]
+    fn limit_zero_refuses_every_attempt() {
+        let limiter = limiter(0, 60);
+        let start = Instant::now();
+        assert_eq!(limiter.check_at(CLIENT, start), Err(60));
+        assert_eq!(limiter.check_at(CLIENT, start + Duration::from_secs(120)), Err(60));
+    }
+}
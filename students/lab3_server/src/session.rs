@@ -0,0 +1,242 @@
+/// This file is used to keep track of the connected clients, each connection being handled by its
+/// own thread. A session can be terminated from another thread, which closes its connection.
+/// The registry is the only place where the login time and the last activity are kept, it decides
+/// when a session expires
+use crate::config::{SessionPolicy, CONFIG};
+use crate::validate_inputs::{username_key, Username};
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, Shutdown, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+lazy_static! {
+    static ref SESSIONS: Mutex<HashMap<u64, Session>> = Mutex::new(HashMap::new());
+}
+
+static LAST_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+struct Session {
+    username: Option<Username>,
+    peer_ip: Option<IpAddr>,
+    login_time: Option<DateTime<Utc>>,
+    last_activity: DateTime<Utc>,
+    /// Handle on the socket of the connection, used to close it
+    stream: Option<TcpStream>,
+}
+
+impl Session {
+    /**
+    Parameters: policy - limits of the sessions
+                now    - current time
+    Return: Option<&str> - Why the logged in user must log in again, None if the session is still valid
+     **/
+    fn expiry_reason(&self, policy: &SessionPolicy, now: DateTime<Utc>) -> Option<&'static str> {
+        let login_time = self.login_time.filter(|_| self.username.is_some())?;
+        if policy
+            .idle_timeout_minutes
+            .is_some_and(|minutes| now - self.last_activity > Duration::minutes(minutes))
+        {
+            return Some("idle for too long");
+        }
+        if policy
+            .max_lifetime_hours
+            .is_some_and(|hours| now - login_time > Duration::hours(hours))
+        {
+            return Some("maximum lifetime reached");
+        }
+        None
+    }
+}
+
+/// Session as sent to the clients
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SessionView {
+    id: u64,
+    username: Option<Username>,
+    peer_ip: Option<IpAddr>,
+    login_time: Option<DateTime<Utc>>,
+    last_activity: DateTime<Utc>,
+    /// Whether it is the session asking for the list
+    current: bool,
+}
+
+// The registry stays usable even if a thread panicked while holding it
+fn sessions() -> MutexGuard<'static, HashMap<u64, Session>> {
+    SESSIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/**
+Parameters: peer_ip - address of the client
+            stream  - handle on the socket of the connection
+Return: u64 - Number of the new anonymous session
+ **/
+pub fn open(peer_ip: Option<IpAddr>, stream: Option<TcpStream>) -> u64 {
+    let id = LAST_SESSION_ID.fetch_add(1, Ordering::Relaxed) + 1;
+    let session = Session {
+        username: None,
+        peer_ip,
+        login_time: None,
+        last_activity: Utc::now(),
+        stream,
+    };
+    sessions().insert(id, session);
+    id
+}
+
+// Logs out the sessions that have expired, their threads notice it at the next action
+fn expire_sessions(sessions: &mut HashMap<u64, Session>) {
+    let now = Utc::now();
+    for (id, session) in sessions.iter_mut() {
+        if let Some(reason) = session.expiry_reason(&CONFIG.session, now) {
+            if let Some(username) = session.username.take() {
+                info!("Session {} of user {} expired: {}", id, username, reason);
+            }
+            session.login_time = None;
+        }
+    }
+}
+
+/**
+Parameter: id - number of the session
+Return: Bool - True if a user is logged in the session, false if it has expired, was terminated or
+        is anonymous
+ **/
+pub fn is_logged_in(id: u64) -> bool {
+    let mut sessions = sessions();
+    expire_sessions(&mut sessions);
+    sessions.get(&id).is_some_and(|session| session.username.is_some())
+}
+
+/// Removes a session once its connection is closed
+pub fn close(id: u64) {
+    sessions().remove(&id);
+}
+
+pub fn record_activity(id: u64) {
+    if let Some(session) = sessions().get_mut(&id) {
+        session.last_activity = Utc::now();
+    }
+}
+
+/**
+Parameters: id       - number of the session
+            username - account logged in, None after a logout
+Return: None - The login time is the time of this call
+ **/
+pub fn set_username(id: u64, username: Option<&Username>) {
+    if let Some(session) = sessions().get_mut(&id) {
+        session.username = username.cloned();
+        session.login_time = username.map(|_| Utc::now());
+    }
+}
+
+/**
+Parameters: current  - number of the session asking for the list
+            username - only lists the sessions of this account if given
+Return: Vec<SessionView> - Sessions sorted by number
+ **/
+pub fn list(current: u64, username: Option<&Username>) -> Vec<SessionView> {
+    let key = username.map(|username| username_key(username));
+    let mut sessions = sessions();
+    // The expired sessions are shown as anonymous, even if their client hasn't sent anything since
+    expire_sessions(&mut sessions);
+    let mut views: Vec<SessionView> = sessions
+        .iter()
+        .filter(|(_, session)| match &key {
+            Some(key) => session.username.as_ref().is_some_and(|name| username_key(name) == *key),
+            None => true,
+        })
+        .map(|(id, session)| SessionView {
+            id: *id,
+            username: session.username.clone(),
+            peer_ip: session.peer_ip,
+            login_time: session.login_time,
+            last_activity: session.last_activity,
+            current: *id == current,
+        })
+        .collect();
+    views.sort_by_key(|view| view.id);
+    views
+}
+
+/// Account logged in a session, None if it is anonymous or doesn't exist
+pub fn username(id: u64) -> Option<Username> {
+    sessions().get(&id)?.username.clone()
+}
+
+/**
+Parameter: id - number of the session
+Return: Bool - True if the session existed, its connection is closed and its thread ends
+ **/
+pub fn terminate(id: u64) -> bool {
+    let session = match sessions().remove(&id) {
+        Some(session) => session,
+        None => return false,
+    };
+    if let Some(stream) = session.stream {
+        // The read waiting for the next action fails, which closes the connection
+        if let Err(e) = stream.shutdown(Shutdown::Both) {
+            warn!("Cannot close the connection of session {}: {}", id, e);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(idle_timeout_minutes: Option<i64>, max_lifetime_hours: Option<i64>) -> SessionPolicy {
+        SessionPolicy { idle_timeout_minutes, max_lifetime_hours }
+    }
+
+    fn session(username: Option<&str>, login_time: DateTime<Utc>, last_activity: DateTime<Utc>) -> Session {
+        let username = username.map(|name| Username::parse(name).unwrap());
+        Session {
+            login_time: username.as_ref().map(|_| login_time),
+            username,
+            peer_ip: None,
+            last_activity,
+            stream: None,
+        }
+    }
+
+    #[test]
+    fn idle_sessions_expire() {
+        let policy = policy(Some(15), None);
+        let login = Utc::now();
+        let session = session(Some("alice"), login, login);
+        assert_eq!(session.expiry_reason(&policy, login + Duration::minutes(15)), None);
+        assert_eq!(session.expiry_reason(&policy, login + Duration::minutes(16)), Some("idle for too long"));
+    }
+
+    #[test]
+    fn activity_does_not_extend_the_lifetime() {
+        let policy = policy(Some(15), Some(8));
+        let login = Utc::now();
+        let now = login + Duration::hours(8) + Duration::minutes(1);
+        let session = session(Some("alice"), login, now);
+        assert_eq!(session.expiry_reason(&policy, now), Some("maximum lifetime reached"));
+        assert_eq!(session.expiry_reason(&policy, login + Duration::hours(8)), None);
+    }
+
+    #[test]
+    fn sessions_without_limits_never_expire() {
+        let policy = policy(None, None);
+        let login = Utc::now();
+        let session = session(Some("alice"), login, login);
+        assert_eq!(session.expiry_reason(&policy, login + Duration::days(365)), None);
+    }
+
+    #[test]
+    fn anonymous_sessions_never_expire() {
+        let policy = policy(Some(15), Some(8));
+        let start = Utc::now();
+        let session = session(None, start, start);
+        assert_eq!(session.expiry_reason(&policy, start + Duration::days(1)), None);
+    }
+}
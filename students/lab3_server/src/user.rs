@@ -1,38 +1,165 @@
 /// This file is used to store and retrieve user accounts from the database
 ///
 /// Tasks todo: - Potential improvements
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use crate::validate_inputs::{Email, PhoneNumber, Username};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UserRole {
     StandardUser,
     HR,
 }
 
+/// Previous password of an account, kept to prevent its reuse
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OldPassword {
+    pub hash: String,
+    pub pepper_version: Option<u32>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UserAccount {
-    username: String,
+    username: Username,
     password: String,
-    phone_number: String,
+    #[serde(default)]
+    pepper_version: Option<u32>,
+    #[serde(default)]
+    password_history: Vec<OldPassword>,
+    #[serde(default)]
+    password_changed: Option<DateTime<Utc>>,
+    phone_number: PhoneNumber,
     role: UserRole,
+    #[serde(default)]
+    profile: Profile,
+    #[serde(default)]
+    history: Vec<FieldChange>,
+}
+
+/// Directory information of an account, every field is optional
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Profile {
+    pub full_name: Option<String>,
+    pub email: Option<Email>,
+    pub job_title: Option<String>,
+    pub department: Option<String>,
+    pub office: Option<String>,
+    pub manager: Option<Username>,
+    pub hire_date: Option<NaiveDate>,
+    /// Free-form notes, only visible to HR
+    pub notes: Option<String>,
+}
+
+/// Fields of an account that can be edited through the profile actions
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileField {
+    PhoneNumber,
+    FullName,
+    Email,
+    JobTitle,
+    Department,
+    Office,
+    Manager,
+    HireDate,
+    Notes,
+    /// Only changed by an approved request, it is never part of a profile update
+    Role,
+}
+
+impl ProfileField {
+    /// Name of the field in the errors sent to the client
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProfileField::PhoneNumber => "phone_number",
+            ProfileField::FullName => "full_name",
+            ProfileField::Email => "email",
+            ProfileField::JobTitle => "job_title",
+            ProfileField::Department => "department",
+            ProfileField::Office => "office",
+            ProfileField::Manager => "manager",
+            ProfileField::HireDate => "hire_date",
+            ProfileField::Notes => "notes",
+            ProfileField::Role => "role",
+        }
+    }
+}
+
+/// Change of a profile field or of the role, kept in the history of the account. The values are stored as text,
+/// in the format accepted by the profile actions, None for an empty field
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FieldChange {
+    /// Number of the change, increasing for each account
+    pub version: u32,
+    pub field: ProfileField,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_by: Username,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Single-use invitation to create an account, the new user chooses the password
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invitation {
+    /// Digest of the code, the code itself is only given to HR when it is created
+    pub code_digest: String,
+    pub username: Username,
+    pub role: UserRole,
+    pub created_by: Username,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Validated new value of a profile field, None clears an optional field
+#[derive(Clone, Debug)]
+pub enum ProfileUpdate {
+    PhoneNumber(PhoneNumber),
+    FullName(Option<String>),
+    Email(Option<Email>),
+    JobTitle(Option<String>),
+    Department(Option<String>),
+    Office(Option<String>),
+    Manager(Option<Username>),
+    HireDate(Option<NaiveDate>),
+    Notes(Option<String>),
+}
+
+impl ProfileUpdate {
+    pub fn field(&self) -> ProfileField {
+        match self {
+            ProfileUpdate::PhoneNumber(_) => ProfileField::PhoneNumber,
+            ProfileUpdate::FullName(_) => ProfileField::FullName,
+            ProfileUpdate::Email(_) => ProfileField::Email,
+            ProfileUpdate::JobTitle(_) => ProfileField::JobTitle,
+            ProfileUpdate::Department(_) => ProfileField::Department,
+            ProfileUpdate::Office(_) => ProfileField::Office,
+            ProfileUpdate::Manager(_) => ProfileField::Manager,
+            ProfileUpdate::HireDate(_) => ProfileField::HireDate,
+            ProfileUpdate::Notes(_) => ProfileField::Notes,
+        }
+    }
 }
 
 impl UserAccount {
     pub fn new(
-        username: String,
+        username: Username,
         password: String,
-        phone_number: String,
+        pepper_version: Option<u32>,
+        phone_number: PhoneNumber,
         role: UserRole,
     ) -> Self {
         Self {
             username,
             password,
+            pepper_version,
+            password_history: Vec::new(),
+            password_changed: Some(Utc::now()),
             phone_number,
             role,
+            profile: Profile::default(),
+            history: Vec::new(),
         }
     }
 
-    pub fn username(&self) -> &str {
+    pub fn username(&self) -> &Username {
         &self.username
     }
 
@@ -40,11 +167,308 @@ impl UserAccount {
         &self.password
     }
 
+    pub fn pepper_version(&self) -> Option<u32> {
+        self.pepper_version
+    }
+
+    pub fn phone_number(&self) -> &PhoneNumber {
+        &self.phone_number
+    }
+
+    pub fn password_history(&self) -> &[OldPassword] {
+        &self.password_history
+    }
+
+    pub fn password_changed(&self) -> Option<DateTime<Utc>> {
+        self.password_changed
+    }
+
+    /**
+    Parameter: max_age - maximum age of a password, None if the passwords don't expire
+    Return: Option<DateTime<Utc>> - Date at which the password expires, None if it was never recorded
+            or never expires
+     **/
+    pub fn password_expiry(&self, max_age: Option<Duration>) -> Option<DateTime<Utc>> {
+        match (self.password_changed?, max_age) {
+            // A password expired on purpose must be changed even without a maximum age
+            (changed, _) if changed == DateTime::UNIX_EPOCH => Some(changed),
+            (changed, max_age) => max_age.map(|max_age| changed + max_age),
+        }
+    }
+
+    /// Forces a password change at the next login, e.g. for a password known by everyone
+    pub fn expire_password(&mut self) {
+        self.password_changed = Some(DateTime::UNIX_EPOCH);
+    }
+
     pub fn role(&self) -> &UserRole {
         &self.role
     }
 
-    pub fn set_phone_number(&mut self, phone_number: String) {
+    /**
+    Parameters: role       - new role of the account
+                changed_by - user who approved the change
+    Return: None - The change is added to the history of the account
+     **/
+    pub fn change_role(&mut self, role: UserRole, changed_by: &Username) {
+        let old_value = self.field_value(ProfileField::Role);
+        self.role = role;
+        self.record_change(ProfileField::Role, old_value, changed_by);
+    }
+
+    /// Records the deletion of the account in its history, as a role removed
+    pub fn record_deletion(&mut self, changed_by: &Username) {
+        let old_value = self.field_value(ProfileField::Role);
+        self.history.push(FieldChange {
+            version: self.next_version(),
+            field: ProfileField::Role,
+            old_value,
+            new_value: None,
+            changed_by: changed_by.clone(),
+            changed_at: Utc::now(),
+        });
+    }
+
+    /// Username of the manager the account reports to
+    pub fn manager(&self) -> Option<&Username> {
+        self.profile.manager.as_ref()
+    }
+
+    /// Replaces the hash of the same password, e.g. after a parameter upgrade
+    pub fn set_password(&mut self, password: String, pepper_version: Option<u32>) {
+        self.password = password;
+        self.pepper_version = pepper_version;
+    }
+
+    /// Records the date of the last change for accounts created before it was tracked
+    pub fn start_password_tracking(&mut self) {
+        self.password_changed.get_or_insert_with(Utc::now);
+    }
+
+    /**
+    Parameters: password       - hash of the new password
+                pepper_version - pepper version used for the new hash
+                history_size   - number of previous passwords to keep
+    Return: None - The current password is moved to the history
+     **/
+    pub fn change_password(&mut self, password: String, pepper_version: Option<u32>, history_size: usize) {
+        let old = OldPassword {
+            hash: std::mem::replace(&mut self.password, password),
+            pepper_version: std::mem::replace(&mut self.pepper_version, pepper_version),
+        };
+        self.password_history.insert(0, old);
+        self.password_history.truncate(history_size);
+        self.password_changed = Some(Utc::now());
+    }
+
+    pub fn set_phone_number(&mut self, phone_number: PhoneNumber) {
         self.phone_number = phone_number;
     }
+
+    pub fn history(&self) -> &[FieldChange] {
+        &self.history
+    }
+
+    /**
+    Parameter: field - profile field to read
+    Return: Option<String> - Value of the field as text, None if it is empty
+     **/
+    pub fn field_value(&self, field: ProfileField) -> Option<String> {
+        let profile = &self.profile;
+        match field {
+            ProfileField::PhoneNumber => Some(self.phone_number.to_string()),
+            ProfileField::FullName => profile.full_name.clone(),
+            ProfileField::Email => profile.email.as_deref().map(str::to_string),
+            ProfileField::JobTitle => profile.job_title.clone(),
+            ProfileField::Department => profile.department.clone(),
+            ProfileField::Office => profile.office.clone(),
+            ProfileField::Manager => profile.manager.as_deref().map(str::to_string),
+            ProfileField::HireDate => profile.hire_date.map(|date| date.to_string()),
+            ProfileField::Notes => profile.notes.clone(),
+            ProfileField::Role => Some(format!("{:?}", self.role)),
+        }
+    }
+
+    /**
+    Parameters: update     - validated new value of a field
+                changed_by - user making the change
+    Return: None - The change is added to the history if the value is different
+     **/
+    pub fn update_profile(&mut self, update: ProfileUpdate, changed_by: &Username) {
+        let field = update.field();
+        let old_value = self.field_value(field);
+
+        let profile = &mut self.profile;
+        match update {
+            ProfileUpdate::PhoneNumber(phone_number) => self.phone_number = phone_number,
+            ProfileUpdate::FullName(full_name) => profile.full_name = full_name,
+            ProfileUpdate::Email(email) => profile.email = email,
+            ProfileUpdate::JobTitle(job_title) => profile.job_title = job_title,
+            ProfileUpdate::Department(department) => profile.department = department,
+            ProfileUpdate::Office(office) => profile.office = office,
+            ProfileUpdate::Manager(manager) => profile.manager = manager,
+            ProfileUpdate::HireDate(hire_date) => profile.hire_date = hire_date,
+            ProfileUpdate::Notes(notes) => profile.notes = notes,
+        }
+
+        self.record_change(field, old_value, changed_by);
+    }
+
+    // Adds the change of a field to the history if its value is different
+    fn record_change(&mut self, field: ProfileField, old_value: Option<String>, changed_by: &Username) {
+        let new_value = self.field_value(field);
+        if new_value != old_value {
+            self.history.push(FieldChange {
+                version: self.next_version(),
+                field,
+                old_value,
+                new_value,
+                changed_by: changed_by.clone(),
+                changed_at: Utc::now(),
+            });
+        }
+    }
+
+    fn next_version(&self) -> u32 {
+        self.history.last().map_or(1, |change| change.version + 1)
+    }
+}
+
+/// Who looks at the directory, decides which fields of the accounts are visible
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Viewer {
+    Anonymous,
+    StandardUser,
+    HR,
+}
+
+impl Viewer {
+    pub fn can_see_roles(&self) -> bool {
+        *self != Viewer::Anonymous
+    }
+}
+
+/// Public view of an account sent to the clients, never contains the password hashes.
+///     - Anonymous users only see the usernames
+///     - Standard users also see the phone numbers and the roles
+///     - HR also sees the date of the last password change
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UserView {
+    username: Username,
+    phone_number: Option<PhoneNumber>,
+    role: Option<UserRole>,
+    password_changed: Option<DateTime<Utc>>,
+}
+
+impl UserView {
+    /**
+    Parameters: user   - account to show
+                viewer - who receives the view
+    Return: UserView - Fields of the account the viewer is allowed to see
+     **/
+    pub fn new(user: &UserAccount, viewer: Viewer) -> UserView {
+        let identified = viewer != Viewer::Anonymous;
+        UserView {
+            username: user.username.clone(),
+            phone_number: identified.then(|| user.phone_number.clone()),
+            role: identified.then_some(user.role),
+            password_changed: if viewer == Viewer::HR { user.password_changed } else { None },
+        }
+    }
+}
+
+/// Profile of an account as sent to the clients, the notes are only filled in for HR
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProfileView {
+    username: Username,
+    role: UserRole,
+    phone_number: PhoneNumber,
+    profile: Profile,
+}
+
+impl ProfileView {
+    /**
+    Parameters: user   - account to show
+                viewer - who receives the view, only anonymous users can't see profiles
+    Return: ProfileView - Fields of the profile the viewer is allowed to see
+     **/
+    pub fn new(user: &UserAccount, viewer: Viewer) -> ProfileView {
+        let mut profile = user.profile.clone();
+        if viewer != Viewer::HR {
+            profile.notes = None;
+        }
+        ProfileView {
+            username: user.username.clone(),
+            role: user.role,
+            phone_number: user.phone_number.clone(),
+            profile,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_inputs::{parse_profile_update, ValidationError};
+
+    fn username(name: &str) -> Username {
+        Username::parse(name).unwrap()
+    }
+
+    fn account() -> UserAccount {
+        let phone_number = PhoneNumber::parse("+41791234567").unwrap();
+        UserAccount::new(username("alice"), "hash".to_string(), None, phone_number, UserRole::StandardUser)
+    }
+
+    #[test]
+    fn profile_changes_are_recorded() {
+        let mut user = account();
+        user.update_profile(ProfileUpdate::JobTitle(Some("Engineer".to_string())), &username("bob"));
+        let change = &user.history()[0];
+        assert_eq!(change.version, 1);
+        assert_eq!(change.field, ProfileField::JobTitle);
+        assert_eq!(change.old_value, None);
+        assert_eq!(change.new_value.as_deref(), Some("Engineer"));
+        assert_eq!(change.changed_by, username("bob"));
+    }
+
+    #[test]
+    fn unchanged_values_are_not_recorded() {
+        let mut user = account();
+        user.update_profile(ProfileUpdate::Office(None), &username("bob"));
+        let phone_number = user.phone_number().clone();
+        user.update_profile(ProfileUpdate::PhoneNumber(phone_number), &username("bob"));
+        assert!(user.history().is_empty());
+    }
+
+    #[test]
+    fn versions_increase_with_each_change() {
+        let mut user = account();
+        user.update_profile(ProfileUpdate::Department(Some("IT".to_string())), &username("bob"));
+        user.update_profile(ProfileUpdate::Department(Some("IT".to_string())), &username("bob"));
+        user.change_role(UserRole::HR, &username("carol"));
+        user.update_profile(ProfileUpdate::Department(None), &username("bob"));
+        let versions: Vec<u32> = user.history().iter().map(|change| change.version).collect();
+        assert_eq!(versions, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn role_changes_are_recorded() {
+        let mut user = account();
+        user.change_role(UserRole::HR, &username("carol"));
+        let change = &user.history()[0];
+        assert_eq!(change.field, ProfileField::Role);
+        assert_eq!(change.old_value.as_deref(), Some("StandardUser"));
+        assert_eq!(change.new_value.as_deref(), Some("HR"));
+    }
+
+    #[test]
+    fn role_changes_cannot_be_reverted() {
+        let mut user = account();
+        user.change_role(UserRole::HR, &username("carol"));
+        // A revert parses the old value again, which is refused for the role
+        let change = &user.history()[0];
+        let old_value = change.old_value.clone().unwrap_or_default();
+        assert!(matches!(parse_profile_update(change.field, &old_value), Err(ValidationError::NotEditable)));
+    }
 }
@@ -1,35 +1,510 @@
 extern crate zxcvbn;
 
+use crate::breach::BloomFilter;
+use crate::config::CONFIG;
+use crate::user::{ProfileField, ProfileUpdate};
+use chrono::{NaiveDate, Utc};
+use lazy_static::lazy_static;
+use phonenumber::Mode;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::Deref;
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{skeleton, MixedScript};
 use zxcvbn::zxcvbn;
 
-static REGEX_USERNAME: &str = r"^([[:alpha:]]){1}([[:alnum:].-_]){2,20}$";
-static REGEX_PHONE: &str = r"^(0)(\d{9})$";
+lazy_static! {
+    static ref USERNAME_REGEX: Regex = Regex::new(r"^\p{L}[\p{L}\p{M}\p{N}._-]{2,20}$").unwrap();
+    static ref EMAIL_REGEX: Regex =
+        Regex::new(r"^[A-Za-z0-9.!#$%&'*+/=?^_`{|}~-]{1,64}@[A-Za-z0-9-]{1,63}(\.[A-Za-z0-9-]{1,63})+$").unwrap();
+    static ref BREACHED_PASSWORDS: Option<BloomFilter> = CONFIG
+        .breached_passwords_filter
+        .as_deref()
+        .map(|path| BloomFilter::load(path).expect("Cannot load breached passwords filter"));
+}
+
+/// Reasons for which a new password is refused, sent to the client
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum PasswordRejection {
+    TooShort { min: usize },
+    TooLong { max: usize },
+    TooWeak {
+        warning: Option<String>,
+        suggestions: Vec<String>,
+    },
+    ContainsBannedWord(String),
+    Breached,
+    Reused,
+}
+
+const MAX_PHONE_LENGTH: usize = 32;
+const MAX_QUERY_LENGTH: usize = 21;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_TEXT_LENGTH: usize = 64;
+const MAX_NOTES_LENGTH: usize = 1000;
+const MAX_PAGE_SIZE: u32 = 50;
+/// Number of hexadecimal digits of the invitation codes
+pub const INVITE_CODE_LENGTH: usize = 32;
+
+/// Reasons for which a client input is refused
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ValidationError {
+    InvalidUsername,
+    ReservedUsername,
+    InvalidPhone,
+    InvalidPassword,
+    PasswordRejected(Vec<PasswordRejection>),
+    InvalidQuery,
+    InvalidPageSize,
+    InvalidCursor,
+    InvalidEmail,
+    InvalidText,
+    InvalidDate,
+    InvalidManager,
+    NotEditable,
+    InvalidInviteCode,
+    CurrentSession,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::InvalidUsername => write!(f, "Invalid username format"),
+            ValidationError::ReservedUsername => write!(f, "This username is reserved"),
+            ValidationError::InvalidPhone => write!(f, "Invalid phone format"),
+            ValidationError::InvalidPassword => write!(f, "Invalid password format"),
+            ValidationError::PasswordRejected(rejections) => {
+                write!(f, "Password refused by the policy: {:?}", rejections)
+            }
+            ValidationError::InvalidQuery => write!(f, "Invalid search query"),
+            ValidationError::InvalidPageSize => write!(f, "Invalid page size"),
+            ValidationError::InvalidCursor => write!(f, "Invalid page cursor"),
+            ValidationError::InvalidEmail => write!(f, "Invalid email format"),
+            ValidationError::InvalidText => write!(f, "Text too long or with invalid characters"),
+            ValidationError::InvalidDate => write!(f, "Invalid date"),
+            ValidationError::InvalidManager => write!(f, "Invalid manager"),
+            ValidationError::NotEditable => write!(f, "Field not editable"),
+            ValidationError::InvalidInviteCode => write!(f, "Invalid invitation code"),
+            ValidationError::CurrentSession => write!(f, "Current session"),
+        }
+    }
+}
+
+/// Username following the username policy, in NFC form
+///
+/// The values read from the database are trusted, every other one goes through `parse`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Username(String);
+
+impl Username {
+    /**
+    Parameter: username - username received from a client
+    Return: Result<Username, ValidationError> - Validated username
+     **/
+    pub fn parse(username: &str) -> Result<Username, ValidationError> {
+        let username: String = username.nfc().collect();
+        // Mixing scripts, e.g. latin and cyrillic letters, is only useful to impersonate someone
+        if USERNAME_REGEX.is_match(&username) && username.as_str().is_single_script() {
+            Ok(Username(username))
+        } else {
+            Err(ValidationError::InvalidUsername)
+        }
+    }
+}
+
+/// Valid phone number in E.164 format
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct PhoneNumber(String);
+
+impl PhoneNumber {
+    /**
+    Parameter: phone - phone to validate, numbers without country code use the default region
+    Return: Result<PhoneNumber, ValidationError> - Number normalised to E.164
+     **/
+    pub fn parse(phone: &str) -> Result<PhoneNumber, ValidationError> {
+        if phone.len() > MAX_PHONE_LENGTH {
+            return Err(ValidationError::InvalidPhone);
+        }
+
+        match phonenumber::parse(Some(CONFIG.default_phone_region), phone) {
+            Ok(number) if number.is_valid() => Ok(PhoneNumber(number.format().mode(Mode::E164).to_string())),
+            _ => Err(ValidationError::InvalidPhone),
+        }
+    }
+}
+
+/// Email address, the domain is stored in lowercase
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Email(String);
+
+impl Email {
+    /**
+    Parameter: email - email address received from the client
+    Return: Result<Email, ValidationError> - Validated address
+     **/
+    pub fn parse(email: &str) -> Result<Email, ValidationError> {
+        let email = email.trim();
+        if email.len() > MAX_EMAIL_LENGTH || !EMAIL_REGEX.is_match(email) {
+            return Err(ValidationError::InvalidEmail);
+        }
+        let (local, domain) = email.split_once('@').ok_or(ValidationError::InvalidEmail)?;
+        Ok(Email(format!("{}@{}", local, domain.to_lowercase())))
+    }
+}
+
+/// Text searched in the usernames, normalised like the username keys so that the search ignores the case
+pub struct SearchQuery(String);
+
+impl SearchQuery {
+    /**
+    Parameter: query - text received from the client, empty to match every username
+    Return: Result<SearchQuery, ValidationError> - Normalised query
+     **/
+    pub fn parse(query: &str) -> Result<SearchQuery, ValidationError> {
+        let query = query.trim();
+        if query.chars().count() <= MAX_QUERY_LENGTH && !query.chars().any(char::is_control) {
+            Ok(SearchQuery(username_key(query)))
+        } else {
+            Err(ValidationError::InvalidQuery)
+        }
+    }
+}
 
 /**
-Parameter: username - username to validate
-Return: Bool - Result of the validation
+Parameter: page_size - number of results per page asked by the client
+Return: Result<usize, ValidationError> - Page size, between 1 and MAX_PAGE_SIZE
  **/
-pub fn validate_username(username: &str) -> bool {
-    Regex::new(REGEX_USERNAME).unwrap().is_match(&username)
+pub fn parse_page_size(page_size: u32) -> Result<usize, ValidationError> {
+    if (1..=MAX_PAGE_SIZE).contains(&page_size) {
+        Ok(page_size as usize)
+    } else {
+        Err(ValidationError::InvalidPageSize)
+    }
+}
+
+/// Invitation code received from a client, 32 hexadecimal digits
+pub struct InviteCode(String);
+
+impl InviteCode {
+    /**
+    Parameter: code - code as typed by the user, the dashes and spaces are ignored
+    Return: Result<InviteCode, ValidationError> - Code in lowercase without separators
+     **/
+    pub fn parse(code: &str) -> Result<InviteCode, ValidationError> {
+        let code: String = code
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if code.len() == INVITE_CODE_LENGTH && code.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(InviteCode(code))
+        } else {
+            Err(ValidationError::InvalidInviteCode)
+        }
+    }
+}
+
+/// Plain password received from a client, never stored
+pub struct Password(String);
+
+impl Password {
+    /**
+    Parameter: password - password received for a login
+    Return: Result<Password, ValidationError> - Password with an acceptable length, the policy
+            itself is only enforced on new passwords
+     **/
+    pub fn parse(password: &str) -> Result<Password, ValidationError> {
+        if !password.is_empty() && password.chars().count() <= CONFIG.password_policy.max_length {
+            Ok(Password(password.to_string()))
+        } else {
+            Err(ValidationError::InvalidPassword)
+        }
+    }
+
+    /**
+    Parameters: password - new password to validate
+                username - username of the account, which can't be part of the password
+    Return: Result<Password, ValidationError> - Password following the password policy
+     **/
+    pub fn parse_new(password: &str, username: &Username) -> Result<Password, ValidationError> {
+        match validate_password(password, username) {
+            Ok(()) => Ok(Password(password.to_string())),
+            Err(rejections) => Err(ValidationError::PasswordRejected(rejections)),
+        }
+    }
+}
+
+impl Deref for Username {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for PhoneNumber {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Email {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for SearchQuery {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for InviteCode {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Password {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Username {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/**
+Parameters: text       - text received from the client, empty to clear the field
+            max_length - maximum number of characters
+            multiline  - whether line breaks are allowed
+Return: Result<Option<String>, ValidationError> - Trimmed text, None if empty
+ **/
+fn parse_text(text: &str, max_length: usize, multiline: bool) -> Result<Option<String>, ValidationError> {
+    let text: String = text.trim().nfc().collect();
+    let invalid_char = text.chars().any(|c| c.is_control() && !(multiline && c == '\n'));
+    if text.chars().count() > max_length || invalid_char {
+        Err(ValidationError::InvalidText)
+    } else if text.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(text))
+    }
+}
+
+/**
+Parameter: date - date received from the client, in the YYYY-MM-DD format
+Return: Result<NaiveDate, ValidationError> - Date between 1900 and today
+ **/
+fn parse_past_date(date: &str) -> Result<NaiveDate, ValidationError> {
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|_| ValidationError::InvalidDate)?;
+    let earliest = NaiveDate::from_ymd_opt(1900, 1, 1).unwrap();
+    if date < earliest || date > Utc::now().date_naive() {
+        return Err(ValidationError::InvalidDate);
+    }
+    Ok(date)
+}
+
+/**
+Parameters: field - profile field to change
+            value - new value received from the client, empty to clear an optional field
+Return: Result<ProfileUpdate, ValidationError> - Validated change
+ **/
+pub fn parse_profile_update(field: ProfileField, value: &str) -> Result<ProfileUpdate, ValidationError> {
+    // Empty values clear the field, the phone number is the only required one
+    let optional = |value: &str| match value.trim() {
+        "" => None,
+        value => Some(value.to_string()),
+    };
+
+    Ok(match field {
+        ProfileField::PhoneNumber => ProfileUpdate::PhoneNumber(PhoneNumber::parse(value)?),
+        ProfileField::FullName => ProfileUpdate::FullName(parse_text(value, MAX_TEXT_LENGTH, false)?),
+        ProfileField::Email => ProfileUpdate::Email(optional(value).map(|v| Email::parse(&v)).transpose()?),
+        ProfileField::JobTitle => ProfileUpdate::JobTitle(parse_text(value, MAX_TEXT_LENGTH, false)?),
+        ProfileField::Department => ProfileUpdate::Department(parse_text(value, MAX_TEXT_LENGTH, false)?),
+        ProfileField::Office => ProfileUpdate::Office(parse_text(value, MAX_TEXT_LENGTH, false)?),
+        ProfileField::Manager => ProfileUpdate::Manager(optional(value).map(|v| Username::parse(&v)).transpose()?),
+        ProfileField::HireDate => ProfileUpdate::HireDate(optional(value).map(|v| parse_past_date(&v)).transpose()?),
+        ProfileField::Notes => ProfileUpdate::Notes(parse_text(value, MAX_NOTES_LENGTH, true)?),
+        // Role changes need the approval of a second HR user
+        ProfileField::Role => return Err(ValidationError::NotEditable),
+    })
+}
+
+/**
+Parameter: username - username to look up
+Return: String - Key under which the account is stored, usernames differing by case are the same
+ **/
+pub fn username_key(username: &str) -> String {
+    username.to_lowercase().nfc().collect()
+}
+
+/**
+Parameters: a - first username
+            b - second username
+Return: Bool - True if both usernames look alike (same UTS #39 skeleton)
+ **/
+pub fn are_confusable(a: &str, b: &str) -> bool {
+    skeleton(&a.to_lowercase()).eq(skeleton(&b.to_lowercase()))
 }
 
 /**
-Parameter: password - password to validate
-Return: Bool - Result of the validation
+Parameter: username - username of a new account
+Return: Bool - True if the username is, or looks like, a reserved name
  **/
-pub fn validate_password(password: &str) -> bool {
-    const MIN_SCORE: u8 = 3;
-    const MIN_CHAR: usize = 8;
-    const MAX_CHAR: usize = 64;
-    let estimate = zxcvbn(password, &[]).unwrap();
-    estimate.score() >= MIN_SCORE && password.len() >= MIN_CHAR && password.len() <= MAX_CHAR
+pub fn is_reserved_username(username: &str) -> bool {
+    CONFIG
+        .reserved_usernames
+        .iter()
+        .any(|reserved| are_confusable(reserved, username))
 }
 
 /**
-Parameter: phone - phone to validate
-Return: Bool - Result of the validation
+Parameters: password - new password to validate
+            username - username of the account, which can't be part of the password
+Return: Result<(), Vec<PasswordRejection>> - Every rule of the password policy that isn't met
  **/
-pub fn validate_phone(phone: &str) -> bool {
-    Regex::new(REGEX_PHONE).unwrap().is_match(&phone)
+fn validate_password(password: &str, username: &str) -> Result<(), Vec<PasswordRejection>> {
+    let policy = &CONFIG.password_policy;
+    let mut rejections = Vec::new();
+
+    let length = password.chars().count();
+    if length < policy.min_length {
+        rejections.push(PasswordRejection::TooShort { min: policy.min_length });
+    }
+    if length > policy.max_length {
+        // Don't run zxcvbn on overly long inputs
+        return Err(vec![PasswordRejection::TooLong { max: policy.max_length }]);
+    }
+
+    let lowercase = password.to_lowercase();
+    let banned_words = policy.banned_words.iter().map(String::as_str).chain([username]);
+    for word in banned_words.clone() {
+        if !word.is_empty() && lowercase.contains(&word.to_lowercase()) {
+            rejections.push(PasswordRejection::ContainsBannedWord(word.to_string()));
+        }
+    }
+
+    let user_inputs: Vec<&str> = banned_words.collect();
+    if let Ok(estimate) = zxcvbn(password, &user_inputs) {
+        if estimate.score() < policy.min_score {
+            let feedback = estimate.feedback().as_ref();
+            rejections.push(PasswordRejection::TooWeak {
+                warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+                suggestions: feedback
+                    .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+                    .unwrap_or_default(),
+            });
+        }
+    }
+
+    if is_breached_password(password) {
+        rejections.push(PasswordRejection::Breached);
+    }
+
+    if rejections.is_empty() {
+        Ok(())
+    } else {
+        Err(rejections)
+    }
+}
+
+/**
+Parameter: None
+Return: None - Compiles the regexes and loads the breached passwords filter at startup
+ **/
+pub fn init() {
+    lazy_static::initialize(&USERNAME_REGEX);
+    lazy_static::initialize(&BREACHED_PASSWORDS);
+}
+
+/**
+Parameter: password - new password to check
+Return: Bool - True if the password appears in the breached passwords list
+ **/
+fn is_breached_password(password: &str) -> bool {
+    match BREACHED_PASSWORDS.as_ref() {
+        Some(filter) => filter.contains(password),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_usernames_are_accepted() {
+        assert!(Username::parse("alice").is_ok());
+        assert!(Username::parse("jean-luc.picard_2").is_ok());
+        assert!(Username::parse("élodie").is_ok());
+        // All the letters in the same script, here cyrillic
+        assert!(Username::parse("сору").is_ok());
+    }
+
+    #[test]
+    fn usernames_are_normalised() {
+        // "e" followed by a combining acute accent is stored as "é"
+        let username = Username::parse("he\u{301}le\u{300}ne").unwrap();
+        assert_eq!(&*username, "hélène");
+    }
+
+    #[test]
+    fn invalid_usernames_are_refused() {
+        assert!(Username::parse("al").is_err());
+        assert!(Username::parse("1alice").is_err());
+        assert!(Username::parse("alice bob").is_err());
+        assert!(Username::parse("ali\\ce").is_err());
+        assert!(Username::parse("ali\"ce").is_err());
+        assert!(Username::parse(&"a".repeat(22)).is_err());
+    }
+
+    #[test]
+    fn mixed_scripts_are_refused() {
+        // Latin with a cyrillic "а"
+        assert!(Username::parse("pаypal").is_err());
+    }
+
+    #[test]
+    fn keys_ignore_the_case() {
+        assert_eq!(username_key("Alice"), username_key("ALICE"));
+        assert_eq!(username_key("Élodie"), "élodie");
+    }
+
+    #[test]
+    fn lookalikes_are_confusable() {
+        assert!(are_confusable("Alice", "alice"));
+        // All cyrillic letters that look like "copy"
+        assert!(are_confusable("copy", "сору"));
+        assert!(are_confusable("paypal", "pаypal"));
+        assert!(!are_confusable("alice", "alicia"));
+    }
+
+    #[test]
+    fn reserved_usernames_and_lookalikes_are_refused() {
+        assert!(is_reserved_username("admin"));
+        assert!(is_reserved_username("Admin"));
+        assert!(is_reserved_username("аdmin"));
+        assert!(!is_reserved_username("administrative"));
+        assert!(!is_reserved_username("alice"));
+    }
 }